@@ -0,0 +1,132 @@
+//! Capturing entities built in a running game into blueprints
+
+use std::{any::TypeId, collections::HashSet};
+
+use bevy::asset::AssetServer;
+use bevy::ecs::{entity::Entity, prelude::ReflectComponent, world::World};
+use bevy::hierarchy::{Children, Parent};
+use bevy::reflect::TypeRegistryArc;
+use tracing::warn;
+
+use crate::{
+    bevy_prints::{reflect::value_from_reflect, BlueprintComponentAdderRegistry},
+    expr::Expr,
+    value::{EntityMap, Value},
+    Blueprint, Error,
+};
+
+/// Reads the components of `entity` back into values, in the order of its
+/// archetype. Components with a registered [`ComponentAdder`] are read back
+/// by it, falling back to reflection for the ones registered with
+/// `#[reflect(Component)]`. Components that are neither, such as
+/// [`Parent`] and [`Children`], are left out, and so are components that
+/// fail to convert, which are logged.
+///
+/// With `with_children` the children of `entity` are captured too, as
+/// nested entities
+///
+/// [`ComponentAdder`]: crate::bevy_prints::ComponentAdder
+pub fn capture_entity(
+    world: &World,
+    entity: Entity,
+    with_children: bool,
+) -> Result<EntityMap<Value>, Error> {
+    let entity_ref = world
+        .get_entity(entity)
+        .ok_or(Error::NoSuchEntity(entity))?;
+
+    let type_registry = world
+        .get_resource::<TypeRegistryArc>()
+        .ok_or(Error::MissingTypeRegistry)?
+        .read();
+    let adder_registry = world.get_resource::<BlueprintComponentAdderRegistry>();
+    let asset_server = world.get_resource::<AssetServer>();
+
+    let mut captured = EntityMap::new();
+    let mut seen = HashSet::new();
+
+    let record = |captured: &mut EntityMap<Value>, name: &str, result| match result {
+        Ok(Some(value)) => captured.add_component(name, value),
+        Ok(None) => {}
+        Err(error) => warn!(?entity, component = name, %error, "Component not captured"),
+    };
+
+    for component_id in entity_ref.archetype().components() {
+        let type_id = match world
+            .components()
+            .get_info(component_id)
+            .and_then(|info| info.type_id())
+        {
+            Some(type_id) => type_id,
+            None => continue,
+        };
+        if type_id == TypeId::of::<Parent>() || type_id == TypeId::of::<Children>() {
+            continue;
+        }
+        let registration = match type_registry.get(type_id) {
+            Some(registration) => registration,
+            None => continue,
+        };
+
+        let name = registration.short_name();
+        seen.insert(name.to_string());
+
+        match adder_registry
+            .and_then(|adders| adders.get_adder(name))
+            .map(|adder| adder.value_from_entity(world, entity))
+        {
+            // Adders that can't read their component back leave it to
+            // reflection
+            None | Some(Ok(None)) => {}
+            Some(result) => {
+                record(&mut captured, name, result);
+                continue;
+            }
+        }
+
+        let result = registration
+            .data::<ReflectComponent>()
+            .and_then(|reflect_component| reflect_component.reflect(world, entity))
+            .map(|reflected| value_from_reflect(&type_registry, asset_server, reflected))
+            .transpose();
+        record(&mut captured, name, result);
+    }
+
+    // Components registered under their own name, in sorted order so the
+    // same entity is always captured the same
+    if let Some(adder_registry) = adder_registry {
+        let mut names: Vec<&String> = adder_registry
+            .entries
+            .keys()
+            .filter(|name| !seen.contains(*name))
+            .collect();
+        names.sort();
+
+        for name in names {
+            let result = adder_registry.entries[name].value_from_entity(world, entity);
+            record(&mut captured, name, result);
+        }
+    }
+
+    if with_children {
+        for child in world.get::<Children>(entity).into_iter().flatten() {
+            let child = capture_entity(world, *child, true)?;
+            captured.add_child(Value::Entity(child));
+        }
+    }
+
+    Ok(captured)
+}
+
+/// Captures `entity` with [`capture_entity`] into a blueprint named `name`,
+/// which can then be saved with [`Blueprint::save`]
+pub fn capture_blueprint(
+    world: &World,
+    entity: Entity,
+    name: &str,
+    with_children: bool,
+) -> Result<Blueprint, Error> {
+    let captured = capture_entity(world, entity, with_children)?;
+
+    Ok(Blueprint::new(name, captured.map(Expr::from)))
+}
@@ -1,30 +1,41 @@
 //! Prints integration for bevy
 
-use std::marker::PhantomData;
+use std::{collections::HashMap, marker::PhantomData};
 
 use bevy::app::{App, Plugin};
-use bevy::asset::{AddAsset, AssetLoader, Assets, BoxedFuture, Handle, LoadContext, LoadedAsset};
+use bevy::asset::{
+    AddAsset, Asset, AssetLoader, AssetPath, Assets, BoxedFuture, Handle, HandleId, HandleUntyped,
+    LoadContext, LoadedAsset,
+};
 use bevy::ecs::{
     entity::Entity,
     prelude::{Component, World},
-    system::{Command, EntityCommands},
+    system::{Command, EntityCommands, IntoExclusiveSystem},
     world::EntityMut,
 };
 
-use bevy::utils::HashMap;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use tracing::info;
 
 use crate::{
-    bevy_prints::spawn::add_to_entity,
-    expr::{Context, Evaluatable},
+    bevy_prints::pending::BlueprintLoadState,
+    expr::{BlueprintLibrary, Context, Evaluatable},
     runtime::SimpleRuntime,
     value::{EntityMap, Value},
     Blueprint, Error,
 };
 
+mod capture;
+mod pending;
+mod reflect;
+mod reload;
 mod spawn;
 
+pub use capture::{capture_blueprint, capture_entity};
+pub use pending::{BlueprintLoadFailed, PendingBlueprint};
+pub use reload::BlueprintInstance;
+pub use spawn::BlueprintSpawnError;
+
 /// Strategy for how add a [`crate::value::Value`] to an entity
 pub trait ComponentAdder {
     fn add_to_entity(
@@ -33,22 +44,47 @@ pub trait ComponentAdder {
         entity: Entity,
         component_name: String,
         component_value: Value,
-    );
+    ) -> Result<(), Error>;
+
+    /// Remove the component this adds from `entity`, used for components a
+    /// blueprint removes
+    fn remove_from_entity(&self, world: &mut World, entity: Entity) -> Result<(), Error>;
+
+    /// Read the component this adds back from `entity`, used to capture
+    /// entities into blueprints. `None` when `entity` doesn't have it or the
+    /// component can't be read back
+    fn value_from_entity(&self, _world: &World, _entity: Entity) -> Result<Option<Value>, Error> {
+        Ok(None)
+    }
 }
 
 type DepInserter = Box<dyn for<'a> Fn(&'a mut EntityMut) + Sync + Send>;
 
+/// Reads a component back from an entity as a [`Value`]
+type ComponentCapture = fn(&World, Entity) -> Result<Option<Value>, Error>;
+
+fn capture_with_serde<T>(world: &World, entity: Entity) -> Result<Option<Value>, Error>
+where
+    T: Serialize + Component,
+{
+    match world.get::<T>(entity) {
+        Some(component) => Ok(Some(Value::from_component(component)?)),
+        None => Ok(None),
+    }
+}
+
 pub struct ComponentInserter<T, D>
 where
-    for<'a> D: Fn(&'a mut World, String, Value) -> T,
+    for<'a> D: Fn(&'a mut World, String, Value) -> Result<T, Error>,
 {
     deserialize: D,
     deps: Vec<DepInserter>,
+    capture: Option<ComponentCapture>,
 }
 
 impl<T, D> ComponentAdder for ComponentInserter<T, D>
 where
-    for<'a> D: Fn(&'a mut World, String, Value) -> T,
+    for<'a> D: Fn(&'a mut World, String, Value) -> Result<T, Error>,
     T: Component,
 {
     fn add_to_entity(
@@ -57,9 +93,9 @@ where
         entity: Entity,
         component_name: String,
         component_value: Value,
-    ) {
+    ) -> Result<(), Error> {
         info!(name = component_name.as_str(), "Adding component");
-        let component: T = (self.deserialize)(world, component_name, component_value);
+        let component: T = (self.deserialize)(world, component_name, component_value)?;
         let mut ent_mut = world.entity_mut(entity);
 
         ent_mut.insert(component);
@@ -67,20 +103,45 @@ where
         for dep in self.deps.iter() {
             dep(&mut ent_mut);
         }
+
+        Ok(())
+    }
+
+    fn remove_from_entity(&self, world: &mut World, entity: Entity) -> Result<(), Error> {
+        world.entity_mut(entity).remove::<T>();
+        Ok(())
+    }
+
+    fn value_from_entity(&self, world: &World, entity: Entity) -> Result<Option<Value>, Error> {
+        match self.capture {
+            Some(capture) => capture(world, entity),
+            None => Ok(None),
+        }
     }
 }
 
 impl<T, D> ComponentInserter<T, D>
 where
-    for<'a> D: Fn(&'a mut World, String, Value) -> T,
+    for<'a> D: Fn(&'a mut World, String, Value) -> Result<T, Error>,
 {
     pub fn new(deserialize: D) -> Self {
         ComponentInserter {
             deserialize,
             deps: Vec::new(),
+            capture: None,
         }
     }
 
+    /// Read the component back with serde when capturing entities into
+    /// blueprints
+    pub fn capture_with_serde(mut self) -> Self
+    where
+        T: Serialize + Component,
+    {
+        self.capture = Some(capture_with_serde::<T>);
+        self
+    }
+
     pub fn depends_on<U>(mut self) -> Self
     where
         U: Component + Default,
@@ -94,32 +155,40 @@ where
     pub fn map_component<F, U>(
         self,
         f: F,
-    ) -> ComponentInserter<U, impl for<'a> Fn(&'a mut World, String, Value) -> U>
+    ) -> ComponentInserter<U, impl for<'a> Fn(&'a mut World, String, Value) -> Result<U, Error>>
     where
         for<'a> F: Fn(&'a mut World, T) -> U,
     {
-        let ComponentInserter { deserialize, deps } = self;
+        let ComponentInserter {
+            deserialize, deps, ..
+        } = self;
 
+        // Reading back `T` says nothing about the `U` inserted instead
         ComponentInserter {
             deserialize: move |world, name, val| {
-                let source_comp = deserialize(world, name, val);
-                f(world, source_comp)
+                let source_comp = deserialize(world, name, val)?;
+                Ok(f(world, source_comp))
             },
             deps,
+            capture: None,
         }
     }
 }
 
 pub fn serde_component<T>(
-) -> ComponentInserter<T, impl for<'a> Fn(&'a mut World, String, Value) -> T>
+) -> ComponentInserter<T, impl for<'a> Fn(&'a mut World, String, Value) -> Result<T, Error>>
 where
     T: DeserializeOwned,
 {
-    ComponentInserter::new(|_world, _component_name, value| value.to_component().unwrap())
+    ComponentInserter::new(|_world, _component_name, value| Ok(value.to_component()?))
 }
 
-/// Use serde to create component and add it
-struct DeserializerComponentAdder<T>(PhantomData<T>);
+/// Use serde to create component and add it, and to read it back when
+/// `capture` is set
+struct DeserializerComponentAdder<T> {
+    capture: Option<ComponentCapture>,
+    marker: PhantomData<T>,
+}
 
 impl<T> ComponentAdder for DeserializerComponentAdder<T>
 where
@@ -131,10 +200,23 @@ where
         entity: Entity,
         component_name: String,
         component_value: Value,
-    ) {
+    ) -> Result<(), Error> {
         info!(name = component_name.as_str(), "Adding component");
-        let component: T = component_value.to_component().unwrap();
+        let component: T = component_value.to_component()?;
         world.entity_mut(entity).insert(component);
+        Ok(())
+    }
+
+    fn remove_from_entity(&self, world: &mut World, entity: Entity) -> Result<(), Error> {
+        world.entity_mut(entity).remove::<T>();
+        Ok(())
+    }
+
+    fn value_from_entity(&self, world: &World, entity: Entity) -> Result<Option<Value>, Error> {
+        match self.capture {
+            Some(capture) => capture(world, entity),
+            None => Ok(None),
+        }
     }
 }
 
@@ -161,11 +243,52 @@ impl BlueprintComponentAdderRegistry {
     {
         self.entries.insert(
             name.to_string(),
-            Box::new(DeserializerComponentAdder::<T>(PhantomData)),
+            Box::new(DeserializerComponentAdder::<T> {
+                capture: None,
+                marker: PhantomData,
+            }),
+        );
+    }
+
+    /// Register a component that is deserialized when added, and serialized
+    /// when an entity is captured into a blueprint
+    pub fn register_component_serde<T>(&mut self, name: &str)
+    where
+        T: Serialize + DeserializeOwned + Component,
+    {
+        self.entries.insert(
+            name.to_string(),
+            Box::new(DeserializerComponentAdder::<T> {
+                capture: Some(capture_with_serde::<T>),
+                marker: PhantomData,
+            }),
         );
     }
 }
 
+/// Functions that can be called from blueprint expressions, starts out with
+/// the standard functions such as `add`, `format` and `vec3`
+pub struct BlueprintFunctions {
+    runtime: SimpleRuntime,
+}
+
+impl Default for BlueprintFunctions {
+    fn default() -> Self {
+        BlueprintFunctions {
+            runtime: SimpleRuntime::with_stdlib(),
+        }
+    }
+}
+
+impl BlueprintFunctions {
+    pub fn register_function<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        self.runtime.register_func(name, f);
+    }
+}
+
 #[derive(Default)]
 pub struct BlueprintAssetLoader;
 
@@ -176,28 +299,120 @@ impl AssetLoader for BlueprintAssetLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let blueprint = Blueprint::load_from_ron_bytes(load_context.path(), bytes)?;
-
-            load_context.set_default_asset(LoadedAsset::new(blueprint));
+            let mut blueprint = Blueprint::load_from_bytes(load_context.path(), bytes)?;
+
+            let dependencies: Vec<AssetPath<'static>> = blueprint
+                .dependencies()
+                .map(|path| AssetPath::from(path).to_owned())
+                .collect();
+
+            blueprint.dependency_handles = dependencies
+                .iter()
+                .map(|path| load_context.get_handle(path.clone()))
+                .collect();
+
+            let dependencies = dependencies
+                .into_iter()
+                .chain(
+                    blueprint
+                        .asset_dependencies()
+                        .map(|path| AssetPath::from(path).to_owned()),
+                )
+                .collect();
+
+            load_context
+                .set_default_asset(LoadedAsset::new(blueprint).with_dependencies(dependencies));
             Ok(())
         })
     }
 
     fn extensions(&self) -> &[&str] {
-        &["bp.ron"]
+        &[
+            "bp.ron",
+            "bp.json",
+            #[cfg(feature = "yaml")]
+            "bp.yaml",
+            #[cfg(feature = "toml")]
+            "bp.toml",
+        ]
+    }
+}
+
+/// Strong handles to the assets referenced with `$asset` by the blueprints
+/// inserted on an entity, the components themselves hold weak handles
+#[derive(Component, Default, Debug)]
+pub struct BlueprintAssets {
+    handles: Vec<HandleUntyped>,
+}
+
+impl BlueprintAssets {
+    pub fn handles(&self) -> impl Iterator<Item = &HandleUntyped> {
+        self.handles.iter()
+    }
+
+    fn extend(&mut self, handles: impl IntoIterator<Item = HandleUntyped>) {
+        for handle in handles {
+            if !self.handles.iter().any(|held| held.id == handle.id) {
+                self.handles.push(handle);
+            }
+        }
+    }
+}
+
+/// Weak handle to the blueprint at asset `path`, blueprints referencing it
+/// keep it loaded
+pub(crate) fn blueprint_handle(path: &str) -> Handle<Blueprint> {
+    Handle::weak(HandleId::from(path))
+}
+
+/// Deserializes an asset path, such as a `{"$blueprint": "path"}` value, into
+/// a weak handle for use with `#[serde(deserialize_with = "...")]`
+pub fn deserialize_handle<'de, D, T>(deserializer: D) -> Result<Handle<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Asset,
+{
+    let path = String::deserialize(deserializer)?;
+    Ok(Handle::weak(HandleId::from(path.as_str())))
+}
+
+impl BlueprintLibrary for Assets<Blueprint> {
+    fn get_blueprint(&self, path: &str) -> Option<&Blueprint> {
+        self.get(&blueprint_handle(path))
     }
 }
 
 fn eval_blueprint(
     world: &mut World,
     blueprint_handle: Handle<Blueprint>,
+    seed: Option<u64>,
+    params: HashMap<String, Value>,
 ) -> Result<EntityMap<Value>, Error> {
-    let blueprints: &Assets<Blueprint> = world.get_resource().unwrap();
-
-    let blueprint = blueprints.get(&blueprint_handle).unwrap();
-    let runtime = SimpleRuntime::new();
+    world.init_resource::<BlueprintFunctions>();
+
+    let blueprints: &Assets<Blueprint> = world
+        .get_resource()
+        .ok_or(Error::MissingResource("Assets<Blueprint>"))?;
+    let functions: &BlueprintFunctions = world
+        .get_resource()
+        .ok_or(Error::MissingResource("BlueprintFunctions"))?;
+
+    let blueprint = blueprints.get(&blueprint_handle).ok_or_else(|| {
+        let path = world
+            .get_resource::<bevy::asset::AssetServer>()
+            .and_then(|asset_server| asset_server.get_handle_path(&blueprint_handle))
+            .map(|path| path.path().to_string_lossy().to_string());
+        Error::BlueprintNotLoaded(path.unwrap_or_else(|| format!("{:?}", blueprint_handle.id)))
+    })?;
+
+    let ctx = match seed {
+        Some(seed) => Context::with_seed(&functions.runtime, seed),
+        None => Context::new(&functions.runtime),
+    }
+    .with_params(params)
+    .with_blueprints(blueprints);
 
-    blueprint.eval_to_entity(&Context::new(&runtime))
+    blueprint.eval_to_entity(&ctx)
 }
 
 pub struct PrintsPlugin;
@@ -205,26 +420,81 @@ pub struct PrintsPlugin;
 impl Plugin for PrintsPlugin {
     fn build(&self, app: &mut bevy::app::App) {
         app.add_asset::<Blueprint>()
-            .init_asset_loader::<BlueprintAssetLoader>();
+            .add_event::<BlueprintLoadFailed>()
+            .add_event::<BlueprintSpawnError>()
+            .init_resource::<BlueprintFunctions>()
+            .init_asset_loader::<BlueprintAssetLoader>()
+            .add_system(pending::insert_pending_blueprints.exclusive_system())
+            .add_system(reload::reload_modified_blueprints);
     }
 }
 
 struct InsertBlueprintCommand {
     entity: Entity,
     blueprint: Handle<Blueprint>,
+    seed: Option<u64>,
+    params: HashMap<String, Value>,
+}
+
+impl InsertBlueprintCommand {
+    fn insert(self, world: &mut World) {
+        info!("Blueprint insert");
+        let seed = self.seed.unwrap_or_else(rand::random);
+        reload::apply_blueprint(world, self.entity, self.blueprint, seed, self.params, None);
+    }
 }
 
 impl Command for InsertBlueprintCommand {
+    /// Inserts the blueprint right away when it is loaded, otherwise it is
+    /// left as a [`PendingBlueprint`] until it is
     fn write(self, world: &mut World) {
-        info!("Blueprint insert");
-        let ent = eval_blueprint(world, self.blueprint).unwrap();
-        info!(data=?&ent, "Blueprint data");
-        add_to_entity(world, self.entity, ent);
+        if world.get_entity(self.entity).is_none() {
+            let error = spawn::ComponentError {
+                entity: self.entity,
+                component: None,
+                error: Error::NoSuchEntity(self.entity),
+            };
+            spawn::send_spawn_errors(world, &self.blueprint, vec![error]);
+            return;
+        }
+
+        match pending::blueprint_load_state(world, &self.blueprint) {
+            Ok(BlueprintLoadState::Loaded) => self.insert(world),
+            Ok(BlueprintLoadState::Loading) => {
+                world.entity_mut(self.entity).insert(PendingBlueprint {
+                    blueprint: self.blueprint,
+                    seed: self.seed,
+                    params: self.params,
+                });
+            }
+            Ok(BlueprintLoadState::Failed(failed)) => {
+                pending::send_load_failed(world, self.entity, self.blueprint, failed)
+            }
+            Err(error) => {
+                let error = spawn::ComponentError {
+                    entity: self.entity,
+                    component: None,
+                    error,
+                };
+                spawn::send_spawn_errors(world, &self.blueprint, vec![error]);
+            }
+        }
     }
 }
 
 pub trait BlueprintEntityCommandExt {
     fn insert_blueprint(&mut self, blueprint: Handle<Blueprint>) -> &mut Self;
+
+    /// Insert blueprint with random functions seeded by `seed`, the same seed
+    /// produces the same components
+    fn insert_blueprint_with_seed(&mut self, blueprint: Handle<Blueprint>, seed: u64) -> &mut Self;
+
+    /// Insert blueprint with values for the parameters it declares
+    fn insert_blueprint_with(
+        &mut self,
+        blueprint: Handle<Blueprint>,
+        params: HashMap<String, Value>,
+    ) -> &mut Self;
 }
 
 impl<'w, 's, 'a> BlueprintEntityCommandExt for EntityCommands<'w, 's, 'a> {
@@ -232,6 +502,38 @@ impl<'w, 's, 'a> BlueprintEntityCommandExt for EntityCommands<'w, 's, 'a> {
         let cmd = InsertBlueprintCommand {
             entity: self.id(),
             blueprint,
+            seed: None,
+            params: Default::default(),
+        };
+
+        self.commands().add(cmd);
+
+        self
+    }
+
+    fn insert_blueprint_with_seed(&mut self, blueprint: Handle<Blueprint>, seed: u64) -> &mut Self {
+        let cmd = InsertBlueprintCommand {
+            entity: self.id(),
+            blueprint,
+            seed: Some(seed),
+            params: Default::default(),
+        };
+
+        self.commands().add(cmd);
+
+        self
+    }
+
+    fn insert_blueprint_with(
+        &mut self,
+        blueprint: Handle<Blueprint>,
+        params: HashMap<String, Value>,
+    ) -> &mut Self {
+        let cmd = InsertBlueprintCommand {
+            entity: self.id(),
+            blueprint,
+            seed: None,
+            params,
         };
 
         self.commands().add(cmd);
@@ -250,6 +552,17 @@ pub trait BlueprintAppExt {
     fn register_blueprint_component_deserializer<T>(&mut self, name: &str) -> &mut Self
     where
         T: DeserializeOwned + Component;
+
+    /// Register component of type `T` to be deserialized from blueprints and
+    /// serialized when capturing entities into blueprints
+    fn register_blueprint_component_serde<T>(&mut self, name: &str) -> &mut Self
+    where
+        T: Serialize + DeserializeOwned + Component;
+
+    /// Register a function that blueprints can call as `{"$name": [args...]}`
+    fn register_blueprint_function<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static;
 }
 
 impl BlueprintAppExt for App {
@@ -274,31 +587,68 @@ impl BlueprintAppExt for App {
         registry.register_component_deserializer::<T>(name);
         self
     }
+
+    fn register_blueprint_component_serde<T>(&mut self, name: &str) -> &mut Self
+    where
+        T: Serialize + DeserializeOwned + Component,
+    {
+        let mut registry = self
+            .world
+            .get_resource_or_insert_with(BlueprintComponentAdderRegistry::default);
+        registry.register_component_serde::<T>(name);
+        self
+    }
+
+    fn register_blueprint_function<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        let mut functions = self
+            .world
+            .get_resource_or_insert_with(BlueprintFunctions::default);
+        functions.register_function(name, f);
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, Mutex};
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
 
     use crate::{
-        bevy_prints::{add_to_entity, BlueprintEntityCommandExt, PrintsPlugin},
-        expr::{Context, EntityExprBuilder, Expr},
+        bevy_prints::{
+            capture_blueprint, deserialize_handle, spawn::add_to_entity, BlueprintAppExt,
+            BlueprintAssets, BlueprintEntityCommandExt, BlueprintLoadFailed, BlueprintSpawnError,
+            ComponentAdder, InsertBlueprintCommand, PendingBlueprint, PrintsPlugin,
+        },
+        expr::{BlueprintLibrary, Context, EntityExprBuilder, Expr},
         runtime::SimpleRuntime,
-        value::Value,
-        Blueprint,
+        value::{EntityMap, Value},
+        Blueprint, Error,
     };
     use bevy::app::App;
-    use bevy::asset::{AssetPlugin, AssetServer, Assets, Handle};
+    use bevy::asset::{
+        AddAsset, AssetLoader, AssetPlugin, AssetServer, Assets, BoxedFuture, Handle, HandleId,
+        LoadContext, LoadedAsset,
+    };
     use bevy::core::CorePlugin;
+    use bevy::hierarchy::{BuildWorldChildren, Children, Parent};
     use bevy::log::LogPlugin;
-    use bevy::reflect::Reflect;
+    use bevy::math::{Quat, Vec3};
+    use bevy::reflect::{Reflect, ReflectDeserialize, ReflectSerialize, TypeUuid};
+    use bevy::transform::components::Transform;
+    use bevy::utils::HashMap;
     use serde::{Deserialize, Serialize};
 
     use bevy::ecs::{
-        prelude::{Component, Res},
+        entity::Entity,
+        event::{Events, ManualEventReader},
+        prelude::{Component, Res, World},
         reflect::ReflectComponent,
-        system::{Commands, ResMut},
-        world::World,
+        system::{Command, Commands, ResMut},
     };
 
     #[derive(Component, Reflect, Default, Debug, Deserialize, Serialize, PartialEq)]
@@ -338,13 +688,55 @@ mod tests {
 
         let entity_value = entity_expr.eval(&Context::new(&runtime)).unwrap();
 
-        add_to_entity(&mut world, ent, entity_value);
+        let mut errors = Vec::new();
+        add_to_entity(&mut world, ent, entity_value, &mut errors);
+        assert!(errors.is_empty());
 
         let test_comp = world.entity(ent).get::<TestComp>().unwrap();
 
         assert_eq!(test_comp, &TestComp { x: 42.0, y: 42.0 });
     }
 
+    #[test]
+    fn test_spawn_without_type_registry() {
+        let mut world = World::new();
+        let ent = world.spawn().id();
+
+        let entity_value = EntityExprBuilder::new()
+            .add_component("TestComp", Expr::Constant(Value::Unit))
+            .build()
+            .eval(&Context::new(&SimpleRuntime::new()))
+            .unwrap();
+
+        let mut errors = Vec::new();
+        add_to_entity(&mut world, ent, entity_value, &mut errors);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].entity, ent);
+        assert!(errors[0].component.is_none());
+        assert!(matches!(errors[0].error, Error::MissingTypeRegistry));
+    }
+
+    #[test]
+    fn test_spawn_on_despawned_entity() {
+        let mut world = World::new();
+        let ent = world.spawn().id();
+        world.despawn(ent);
+
+        let mut entity_value = EntityMap::new();
+        entity_value.add_component("TestComp", Value::Unit);
+        entity_value.add_child(Value::Entity(EntityMap::new()));
+
+        let mut errors = Vec::new();
+        let children = add_to_entity(&mut world, ent, entity_value, &mut errors);
+
+        assert!(children.is_empty());
+        assert_eq!(world.entities().len(), 0);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].component.is_none());
+        assert!(matches!(errors[0].error, Error::NoSuchEntity(e) if e == ent));
+    }
+
     fn start_up_system(mut bps: ResMut<Assets<Blueprint>>, mut commands: Commands) {
         let entity_expr = EntityExprBuilder::new()
             .add_component(
@@ -400,35 +792,169 @@ mod tests {
     }
 
     #[test]
-    fn test_asset_load() {
+    fn test_registered_function_spawn() {
         let mut app = App::new();
 
         app.add_plugin(CorePlugin)
-            .add_plugin(LogPlugin)
             .add_plugin(AssetPlugin)
             .add_plugin(PrintsPlugin)
-            .register_type::<TestComp>();
+            .register_type::<TestComp>()
+            .register_blueprint_function("double", |args| match args {
+                [Value::F32(v)] => Ok(Value::F32(v * 2.0)),
+                _ => Err(Error::UnexpectedType {
+                    unexpected: "args",
+                    expected: "f32",
+                }),
+            });
+
+        app.add_startup_system(
+            |mut bps: ResMut<Assets<Blueprint>>, mut commands: Commands| {
+                let entity_expr = EntityExprBuilder::new()
+                    .add_component(
+                        "TestComp",
+                        Expr::KeyMap(
+                            vec![
+                                (
+                                    "x".to_string(),
+                                    Expr::Func(
+                                        "double".to_string(),
+                                        vec![Expr::Constant(Value::F32(21.0))],
+                                    ),
+                                ),
+                                ("y".to_string(), Expr::Constant(Value::F32(42.0))),
+                            ]
+                            .into_iter()
+                            .collect(),
+                        ),
+                    )
+                    .build();
+
+                let bp_handle = bps.add(Blueprint::new("test_bp", entity_expr));
+
+                commands.spawn().insert_blueprint(bp_handle);
+            },
+        );
+
+        app.update();
 
-        let mut handle = Arc::new(Mutex::new(None));
+        let test_comps: Vec<&TestComp> = app.world.query::<&TestComp>().iter(&app.world).collect();
 
-        let handle_startup = handle.clone();
+        assert_eq!(test_comps, vec![&TestComp { x: 42.0, y: 42.0 }]);
+    }
+
+    #[test]
+    fn test_seeded_spawn() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
 
         app.add_startup_system(
-            move |mut bps: ResMut<Assets<Blueprint>>,
-                  asset_server: Res<AssetServer>,
-                  mut commands: Commands| {
-                let bp_handle: Handle<Blueprint> = asset_server.load("blueprints/test.bp.ron");
+            |mut bps: ResMut<Assets<Blueprint>>, mut commands: Commands| {
+                let rand_range = Expr::Func(
+                    "rand_range".to_string(),
+                    vec![
+                        Expr::Constant(Value::F32(-100.0)),
+                        Expr::Constant(Value::F32(100.0)),
+                    ],
+                );
+                let entity_expr = EntityExprBuilder::new()
+                    .add_component(
+                        "TestComp",
+                        Expr::KeyMap(
+                            vec![
+                                ("x".to_string(), rand_range.clone()),
+                                ("y".to_string(), rand_range),
+                            ]
+                            .into_iter()
+                            .collect(),
+                        ),
+                    )
+                    .build();
+
+                let bp_handle = bps.add(Blueprint::new("test_bp", entity_expr));
+
+                commands
+                    .spawn()
+                    .insert_blueprint_with_seed(bp_handle.clone(), 7);
+                commands.spawn().insert_blueprint_with_seed(bp_handle, 7);
+            },
+        );
+
+        app.update();
+
+        let test_comps: Vec<&TestComp> = app.world.query::<&TestComp>().iter(&app.world).collect();
+
+        assert_eq!(test_comps.len(), 2);
+        assert_eq!(test_comps[0], test_comps[1]);
+    }
+
+    #[test]
+    fn test_spawn_with_params() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
 
+        app.add_startup_system(
+            |mut bps: ResMut<Assets<Blueprint>>, mut commands: Commands| {
+                let bp = Blueprint::load_from_ron_bytes(
+                    Path::new("test_bp.bp.ron"),
+                    br#"
                 {
-                    let mut handle_mut = handle_startup.lock().unwrap();
-                    *handle_mut = Some(bp_handle);
+                    "$params": { "x": (type: "f32"), "y": (default: 42.0) },
+                    "TestComp": (x: { "$param": "x" }, y: { "$param": "y" }),
                 }
+                "#,
+                )
+                .unwrap();
+
+                let bp_handle = bps.add(bp);
+
+                commands.spawn().insert_blueprint_with(
+                    bp_handle,
+                    vec![("x".to_string(), Value::I32(7))].into_iter().collect(),
+                );
             },
         );
 
+        app.update();
+
+        let test_comps: Vec<&TestComp> = app.world.query::<&TestComp>().iter(&app.world).collect();
+
+        assert_eq!(test_comps, vec![&TestComp { x: 7.0, y: 42.0 }]);
+    }
+
+    #[test]
+    fn test_asset_load() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(LogPlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let handle = Arc::new(Mutex::new(None));
+
+        let handle_startup = handle.clone();
+
+        app.add_startup_system(move |asset_server: Res<AssetServer>| {
+            let bp_handle: Handle<Blueprint> = asset_server.load("blueprints/test.bp.ron");
+
+            {
+                let mut handle_mut = handle_startup.lock().unwrap();
+                *handle_mut = Some(bp_handle);
+            }
+        });
+
         let mut ticks = 0;
 
-        let loaded_blueprint = loop {
+        let _loaded_blueprint = loop {
             app.update();
 
             let handle_lock = handle.lock().unwrap();
@@ -449,4 +975,1550 @@ mod tests {
             }
         };
     }
+
+    /// Loads the blueprint at `path` through the asset server
+    fn assert_asset_loads(path: &str) {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin);
+
+        let bp_handle: Handle<Blueprint> = app.world.resource::<AssetServer>().load(path);
+
+        let mut ticks = 0;
+
+        while app
+            .world
+            .resource::<Assets<Blueprint>>()
+            .get(&bp_handle)
+            .is_none()
+        {
+            app.update();
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for {} to load", path);
+            }
+        }
+
+        let events = app.world.resource::<Events<BlueprintLoadFailed>>();
+        assert_eq!(ManualEventReader::default().iter(events).count(), 0);
+    }
+
+    #[test]
+    fn test_json_asset_load() {
+        assert_asset_loads("blueprints/example.bp.json");
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_asset_load() {
+        assert_asset_loads("blueprints/example.bp.yaml");
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_asset_load() {
+        assert_asset_loads("blueprints/example.bp.toml");
+    }
+
+    #[test]
+    fn test_extends_asset_load() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let bp_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/test_extends.bp.ron");
+
+        let mut ticks = 0;
+
+        loop {
+            app.update();
+
+            let blueprints = app.world.resource::<Assets<Blueprint>>();
+
+            if blueprints.get(&bp_handle).is_some()
+                && blueprints
+                    .get_blueprint("blueprints/test_base.bp.ron")
+                    .is_some()
+            {
+                break;
+            }
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for blueprint assets to load");
+            }
+        }
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert_eq!(
+            app.world.entity(ent).get::<TestComp>(),
+            Some(&TestComp { x: 1.0, y: 5.0 })
+        );
+    }
+
+    #[derive(Component, Debug, Deserialize, PartialEq)]
+    struct Collider {
+        radius: f32,
+    }
+
+    #[test]
+    fn test_remove_component_on_reinsert() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>()
+            .register_blueprint_component_deserializer::<Collider>("Collider");
+
+        let base = Blueprint::load_from_ron_bytes(
+            Path::new("base.bp.ron"),
+            br#"{ "TestComp": (x: 1.0, y: 2.0), "Collider": (radius: 1.0) }"#,
+        )
+        .unwrap();
+        let stripped = Blueprint::load_from_ron_bytes(
+            Path::new("stripped.bp.ron"),
+            br#"{ "$remove": ["TestComp", "Collider"] }"#,
+        )
+        .unwrap();
+
+        let mut blueprints = app.world.resource_mut::<Assets<Blueprint>>();
+        let base = blueprints.add(base);
+        let stripped = blueprints.add(stripped);
+
+        let ent = app.world.spawn().id();
+
+        let insert = |world: &mut World, blueprint| {
+            InsertBlueprintCommand {
+                entity: ent,
+                blueprint,
+                seed: None,
+                params: Default::default(),
+            }
+            .write(world)
+        };
+
+        insert(&mut app.world, base);
+        assert!(app.world.entity(ent).contains::<TestComp>());
+        assert!(app.world.entity(ent).contains::<Collider>());
+
+        insert(&mut app.world, stripped);
+        assert_eq!(app.world.entity(ent).get::<TestComp>(), None);
+        assert_eq!(app.world.entity(ent).get::<Collider>(), None);
+    }
+
+    #[test]
+    fn test_despawn_children_on_reinsert() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("parent.bp.ron"),
+            br#"{ "$children": [{ "TestComp": (x: 1.0, y: 1.0) }] }"#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        let insert = |world: &mut World| {
+            InsertBlueprintCommand {
+                entity: ent,
+                blueprint: bp_handle.clone(),
+                seed: None,
+                params: Default::default(),
+            }
+            .write(world)
+        };
+
+        insert(&mut app.world);
+        let first_child = app.world.entity(ent).get::<Children>().unwrap()[0];
+
+        insert(&mut app.world);
+        let children = app.world.entity(ent).get::<Children>().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_ne!(children[0], first_child);
+        assert!(app.world.get_entity(first_child).is_none());
+    }
+
+    #[test]
+    fn test_spawn_children() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let weapon = Blueprint::load_from_ron_bytes(
+            Path::new("weapon.bp.ron"),
+            br#"{ "TestComp": (x: 1.0, y: 1.0) }"#,
+        )
+        .unwrap();
+        let unit = Blueprint::load_from_ron_bytes(
+            Path::new("unit.bp.ron"),
+            br#"
+            {
+                "TestComp": (x: 0.0, y: 0.0),
+                "$children": [
+                    "weapon.bp.ron",
+                    { "$children": [{ "TestComp": (x: 2.0, y: 2.0) }] },
+                ],
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut blueprints = app.world.resource_mut::<Assets<Blueprint>>();
+        blueprints.set_untracked(HandleId::from("weapon.bp.ron"), weapon);
+        let unit = blueprints.add(unit);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: unit,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let children: Vec<Entity> = app
+            .world
+            .entity(ent)
+            .get::<Children>()
+            .expect("unit has children")
+            .iter()
+            .copied()
+            .collect();
+
+        let (weapon, hitbox) = match children.as_slice() {
+            [weapon, hitbox] => (*weapon, *hitbox),
+            other => panic!("Expected two children, got {:?}", other),
+        };
+
+        for child in [weapon, hitbox] {
+            assert_eq!(app.world.entity(child).get::<Parent>().unwrap().get(), ent);
+        }
+
+        assert_eq!(
+            app.world.entity(weapon).get::<TestComp>(),
+            Some(&TestComp { x: 1.0, y: 1.0 })
+        );
+
+        let grandchild = app.world.entity(hitbox).get::<Children>().unwrap()[0];
+        assert_eq!(
+            app.world.entity(grandchild).get::<TestComp>(),
+            Some(&TestComp { x: 2.0, y: 2.0 })
+        );
+    }
+
+    #[derive(Component, Deserialize)]
+    struct TestSpawner {
+        #[serde(deserialize_with = "deserialize_handle")]
+        spawns: Handle<Blueprint>,
+    }
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component)]
+    struct TestReflectSpawner {
+        spawns: Handle<Blueprint>,
+    }
+
+    #[test]
+    fn test_blueprint_handle_field() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestReflectSpawner>()
+            .register_blueprint_component_deserializer::<TestSpawner>("TestSpawner");
+
+        let bp_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/test_spawner.bp.ron");
+        let base_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .get_handle("blueprints/test_base.bp.ron");
+
+        let mut ticks = 0;
+
+        loop {
+            app.update();
+
+            let blueprints = app.world.resource::<Assets<Blueprint>>();
+
+            if blueprints.get(&bp_handle).is_some() && blueprints.get(&base_handle).is_some() {
+                break;
+            }
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for blueprint assets to load");
+            }
+        }
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let spawner = app.world.entity(ent).get::<TestSpawner>().unwrap();
+        assert_eq!(spawner.spawns, base_handle);
+
+        let spawner = app.world.entity(ent).get::<TestReflectSpawner>().unwrap();
+        assert_eq!(spawner.spawns, base_handle);
+    }
+
+    #[derive(Component, Deserialize)]
+    struct TestModel {
+        #[serde(deserialize_with = "deserialize_handle")]
+        model: Handle<Blueprint>,
+    }
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component)]
+    struct TestReflectModel(Handle<Blueprint>);
+
+    #[test]
+    fn test_asset_handle_field() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestReflectModel>()
+            .register_blueprint_component_deserializer::<TestModel>("TestModel");
+
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("model.bp.ron"),
+            br#"
+            {
+                "TestModel": (model: { "$asset": "blueprints/test_base.bp.ron" }),
+                "TestReflectModel": [{ "$asset": "blueprints/test_base.bp.ron" }],
+            }
+            "#,
+        )
+        .unwrap();
+        let blueprint = app.world.resource_mut::<Assets<Blueprint>>().add(blueprint);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let base_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .get_handle("blueprints/test_base.bp.ron");
+
+        let entity = app.world.entity(ent);
+        assert_eq!(entity.get::<TestModel>().unwrap().model, base_handle);
+        assert_eq!(entity.get::<TestReflectModel>().unwrap().0, base_handle);
+        assert_eq!(
+            entity
+                .get::<BlueprintAssets>()
+                .unwrap()
+                .handles()
+                .map(|handle| handle.id)
+                .collect::<Vec<_>>(),
+            vec![base_handle.id]
+        );
+
+        let mut ticks = 0;
+
+        while app
+            .world
+            .resource::<Assets<Blueprint>>()
+            .get(&base_handle)
+            .is_none()
+        {
+            app.update();
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for the referenced asset to load");
+            }
+        }
+
+        let captured = capture_blueprint(&app.world, ent, "model", false).unwrap();
+        assert_eq!(
+            captured.entity.get_component("TestReflectModel"),
+            Some(&Expr::Named(
+                "TestReflectModel".to_string(),
+                Box::new(Expr::Vec(vec![Expr::Constant(Value::Blueprint(
+                    "blueprints/test_base.bp.ron".to_string()
+                ))]))
+            ))
+        );
+    }
+
+    #[derive(Debug, TypeUuid)]
+    #[uuid = "6f1d2b42-58a4-4c7e-9a43-0f1a8e2f7c15"]
+    struct TestText(String);
+
+    #[derive(Default)]
+    struct TestTextLoader;
+
+    impl AssetLoader for TestTextLoader {
+        fn load<'a>(
+            &'a self,
+            bytes: &'a [u8],
+            load_context: &'a mut LoadContext,
+        ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+            Box::pin(async move {
+                let text = String::from_utf8(bytes.to_vec())?;
+                load_context.set_default_asset(LoadedAsset::new(TestText(text)));
+                Ok(())
+            })
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["txt"]
+        }
+    }
+
+    #[derive(Component, Deserialize)]
+    struct TestLabel {
+        #[serde(deserialize_with = "deserialize_handle")]
+        text: Handle<TestText>,
+    }
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component)]
+    struct TestReflectLabel(Handle<TestText>);
+
+    #[test]
+    fn test_other_asset_handle_field() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .add_asset::<TestText>()
+            .init_asset_loader::<TestTextLoader>()
+            .register_type::<TestReflectLabel>()
+            .register_blueprint_component_deserializer::<TestLabel>("TestLabel");
+
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("label.bp.ron"),
+            br#"
+            {
+                "TestLabel": (text: { "$asset": "blueprints/test_text.txt" }),
+                "TestReflectLabel": [{ "$asset": "blueprints/test_text.txt" }],
+            }
+            "#,
+        )
+        .unwrap();
+        let blueprint = app.world.resource_mut::<Assets<Blueprint>>().add(blueprint);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let text_handle: Handle<TestText> = app
+            .world
+            .resource::<AssetServer>()
+            .get_handle("blueprints/test_text.txt");
+
+        let entity = app.world.entity(ent);
+        assert_eq!(entity.get::<TestLabel>().unwrap().text, text_handle);
+        assert_eq!(entity.get::<TestReflectLabel>().unwrap().0, text_handle);
+
+        let mut ticks = 0;
+
+        while app
+            .world
+            .resource::<Assets<TestText>>()
+            .get(&text_handle)
+            .is_none()
+        {
+            app.update();
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for the referenced asset to load");
+            }
+        }
+
+        let texts = app.world.resource::<Assets<TestText>>();
+        let entity = app.world.entity(ent);
+        assert_eq!(
+            texts
+                .get(&entity.get::<TestLabel>().unwrap().text)
+                .unwrap()
+                .0,
+            "Hello from a blueprint\n"
+        );
+        assert_eq!(
+            texts
+                .get(&entity.get::<TestReflectLabel>().unwrap().0)
+                .unwrap()
+                .0,
+            "Hello from a blueprint\n"
+        );
+    }
+
+    #[test]
+    fn test_deferred_insert() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let bp_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/test_extends.bp.ron");
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert!(app.world.entity(ent).contains::<PendingBlueprint>());
+
+        let mut ticks = 0;
+
+        while app.world.entity(ent).contains::<PendingBlueprint>() {
+            app.update();
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for pending blueprint to be inserted");
+            }
+        }
+
+        assert_eq!(
+            app.world.entity(ent).get::<TestComp>(),
+            Some(&TestComp { x: 1.0, y: 5.0 })
+        );
+    }
+
+    #[test]
+    fn test_insert_on_despawned_entity() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let loaded = app
+            .world
+            .resource_mut::<Assets<Blueprint>>()
+            .add(Blueprint::new("test_bp", EntityExprBuilder::new().build()));
+        let loading: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/test_extends.bp.ron");
+
+        for bp_handle in [loaded, loading] {
+            let ent = app.world.spawn().id();
+            app.world.despawn(ent);
+
+            InsertBlueprintCommand {
+                entity: ent,
+                blueprint: bp_handle.clone(),
+                seed: None,
+                params: Default::default(),
+            }
+            .write(&mut app.world);
+
+            let events = app.world.resource::<Events<BlueprintSpawnError>>();
+            let mut reader = ManualEventReader::default();
+            let errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].entity, ent);
+            assert_eq!(errors[0].blueprint, bp_handle);
+            assert!(matches!(errors[0].error, Error::NoSuchEntity(e) if e == ent));
+
+            app.world
+                .resource_mut::<Events<BlueprintSpawnError>>()
+                .clear();
+        }
+    }
+
+    #[test]
+    fn test_insert_without_blueprint_assets() {
+        let mut world = World::new();
+        world.init_resource::<Events<BlueprintSpawnError>>();
+        let ent = world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: Handle::default(),
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut world);
+
+        let events = world.resource::<Events<BlueprintSpawnError>>();
+        let mut reader = ManualEventReader::default();
+        let errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].error,
+            Error::MissingResource("Assets<Blueprint>")
+        ));
+    }
+
+    #[test]
+    fn test_deferred_insert_load_failed() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin);
+
+        let bp_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/missing.bp.ron");
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle.clone(),
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let mut reader = ManualEventReader::<BlueprintLoadFailed>::default();
+        let mut ticks = 0;
+
+        let failed = loop {
+            app.update();
+
+            let events = app.world.resource::<Events<BlueprintLoadFailed>>();
+            if let Some(failed) = reader.iter(events).next() {
+                break failed;
+            }
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for blueprint load to fail");
+            }
+        };
+
+        assert_eq!(failed.entity, ent);
+        assert_eq!(failed.blueprint, bp_handle);
+        assert_eq!(failed.path.as_deref(), Some("blueprints/missing.bp.ron"));
+        assert!(!app.world.entity(ent).contains::<PendingBlueprint>());
+    }
+
+    #[test]
+    fn test_deferred_insert_asset_failed() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .add_asset::<TestText>()
+            .init_asset_loader::<TestTextLoader>()
+            .register_type::<TestReflectLabel>();
+
+        let bp_handle: Handle<Blueprint> = app
+            .world
+            .resource::<AssetServer>()
+            .load("blueprints/test_missing_asset.bp.ron");
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle.clone(),
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let mut reader = ManualEventReader::<BlueprintLoadFailed>::default();
+        let mut ticks = 0;
+
+        let failed = loop {
+            app.update();
+
+            let events = app.world.resource::<Events<BlueprintLoadFailed>>();
+            if let Some(failed) = reader.iter(events).next() {
+                break failed;
+            }
+
+            ticks += 1;
+
+            if ticks > 100 {
+                panic!("Timeout waiting for the referenced asset to fail");
+            }
+        };
+
+        assert_eq!(failed.entity, ent);
+        assert_eq!(failed.blueprint, bp_handle);
+        assert_eq!(failed.path.as_deref(), Some("blueprints/missing.txt"));
+        let entity = app.world.entity(ent);
+        assert!(!entity.contains::<PendingBlueprint>());
+        assert!(!entity.contains::<TestReflectLabel>());
+    }
+
+    #[derive(Component)]
+    struct RuntimeMarker;
+
+    #[test]
+    fn test_hot_reload() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>()
+            .register_blueprint_component_deserializer::<Collider>("Collider");
+
+        let load = |path: &str, source: &str| {
+            Blueprint::load_from_ron_bytes(Path::new(path), source.as_bytes()).unwrap()
+        };
+
+        let mut blueprints = app.world.resource_mut::<Assets<Blueprint>>();
+        blueprints.set_untracked(
+            HandleId::from("base.bp.ron"),
+            load(
+                "base.bp.ron",
+                r#"
+                {
+                    "TestComp": (x: { "$rand_range": [0.0, 1.0] }, y: 1.0),
+                    "Collider": (radius: 1.0),
+                    "$children": [{ "TestComp": (x: 0.0, y: 0.0) }],
+                }
+                "#,
+            ),
+        );
+        let derived = blueprints.add(load(
+            "derived.bp.ron",
+            r#"{ "$extends": "base.bp.ron", "TestComp": (y: 2.0) }"#,
+        ));
+
+        let ent = app.world.spawn().insert(RuntimeMarker).id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: derived,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let x = app.world.entity(ent).get::<TestComp>().unwrap().x;
+        assert!(app.world.entity(ent).contains::<Collider>());
+
+        app.world.resource_mut::<Assets<Blueprint>>().set_untracked(
+            HandleId::from("base.bp.ron"),
+            load(
+                "base.bp.ron",
+                r#"
+                {
+                    "TestComp": (x: { "$rand_range": [0.0, 1.0] }, y: 3.0),
+                    "$children": [{ "TestComp": (x: 5.0, y: 5.0) }],
+                }
+                "#,
+            ),
+        );
+
+        app.update();
+        app.update();
+
+        let entity = app.world.entity(ent);
+        assert_eq!(entity.get::<TestComp>(), Some(&TestComp { x, y: 2.0 }));
+        assert!(!entity.contains::<Collider>());
+        assert!(entity.contains::<RuntimeMarker>());
+
+        let children = entity.get::<Children>().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            app.world.entity(children[0]).get::<TestComp>(),
+            Some(&TestComp { x: 5.0, y: 5.0 })
+        );
+    }
+
+    #[test]
+    fn test_spawn_errors() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>()
+            .register_blueprint_component_deserializer::<Collider>("Collider");
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("errors.bp.ron"),
+            br#"
+            {
+                "TestComp": (x: 1.0, y: 2.0),
+                "NotAComponent": (x: 1.0),
+                "Collider": (diameter: 1.0),
+            }
+            "#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle.clone(),
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert_eq!(
+            app.world.entity(ent).get::<TestComp>(),
+            Some(&TestComp { x: 1.0, y: 2.0 })
+        );
+        assert!(!app.world.entity(ent).contains::<Collider>());
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        let mut reader = ManualEventReader::default();
+        let mut errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+        errors.sort_by(|a, b| a.component.cmp(&b.component));
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|error| error.entity == ent && error.blueprint == bp_handle));
+        assert_eq!(errors[0].component.as_deref(), Some("Collider"));
+        assert!(matches!(errors[0].error, Error::ToComponentError(_)));
+        assert_eq!(errors[1].component.as_deref(), Some("NotAComponent"));
+        assert!(matches!(
+            &errors[1].error,
+            Error::UnknownComponent(name) if name == "NotAComponent"
+        ));
+    }
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Probe {
+        offset: (f32, f32),
+    }
+
+    #[test]
+    fn test_spawn_unregistered_field() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Probe>();
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("unregistered.bp.ron"),
+            br#"{ "Probe": (offset: [1, 2]) }"#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert!(!app.world.entity(ent).contains::<Probe>());
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        let mut reader = ManualEventReader::default();
+        let errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].component.as_deref(), Some("Probe"));
+        assert!(matches!(
+            &errors[0].error,
+            Error::UnregisteredType(name) if name == "(f32, f32)"
+        ));
+    }
+
+    #[test]
+    fn test_spawn_wrong_struct_name() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<TestComp>();
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("wrong_name.bp.ron"),
+            br#"{ "TestComp": Vec3(x: 1.0, y: 2.0) }"#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert!(!app.world.entity(ent).contains::<TestComp>());
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        let mut reader = ManualEventReader::default();
+        let errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0].error,
+            Error::UnexpectedName { name, expected } if name == "Vec3" && expected == "TestComp"
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_wrong_struct_name() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_blueprint_component_deserializer::<Collider>("Collider")
+            .register_blueprint_component_deserializer::<Collider>("Hitbox");
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("wrong_name.bp.ron"),
+            br#"{
+                "Collider": Collider(radius: 2.0),
+                "Hitbox": Sphere(radius: 1.0),
+            }"#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        assert_eq!(
+            app.world.entity(ent).get::<Collider>(),
+            Some(&Collider { radius: 2.0 })
+        );
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        let mut reader = ManualEventReader::default();
+        let errors: Vec<&BlueprintSpawnError> = reader.iter(events).collect();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].component.as_deref(), Some("Hitbox"));
+        assert!(matches!(
+            &errors[0].error,
+            Error::ToComponentError(error) if error.to_string() == "`Sphere` given for a `Collider`"
+        ));
+    }
+
+    #[derive(Reflect, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+    #[reflect_value(Debug, PartialEq, Deserialize, Serialize)]
+    enum Team {
+        #[default]
+        Red,
+        Blue,
+    }
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Patrol {
+        points: Vec<Vec3>,
+        weights: HashMap<String, i32>,
+        offset: (f32, f32),
+        team: Team,
+    }
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Speed(f32);
+
+    #[test]
+    fn test_reflect_nested_values() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Transform>()
+            .register_type::<Patrol>()
+            .register_type::<Vec<Vec3>>()
+            .register_type::<HashMap<String, i32>>()
+            .register_type::<(f32, f32)>()
+            .register_type::<Team>()
+            .register_type::<Speed>();
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("nested.bp.ron"),
+            br#"
+            {
+                "Transform": (
+                    translation: (x: 1.0, y: 2.0, z: 3.0),
+                    rotation: [0.0, 0.0, 1.0, 0.0],
+                    scale: [2.0, 2.0, 2.0],
+                ),
+                "Patrol": (
+                    points: [(x: 1.0, y: 0.0, z: 0.0), [0.0, 1.0, 0.0]],
+                    weights: { "a": 1, "b": 2 },
+                    offset: [0.5, 1.5],
+                    team: "Blue",
+                ),
+                "Speed": 2.5,
+            }
+            "#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        assert_eq!(ManualEventReader::default().iter(events).count(), 0);
+
+        let entity = app.world.entity(ent);
+        assert_eq!(
+            entity.get::<Transform>(),
+            Some(&Transform {
+                translation: Vec3::new(1.0, 2.0, 3.0),
+                rotation: Quat::from_xyzw(0.0, 0.0, 1.0, 0.0),
+                scale: Vec3::splat(2.0),
+            })
+        );
+        assert_eq!(
+            entity.get::<Patrol>(),
+            Some(&Patrol {
+                points: vec![Vec3::X, Vec3::Y],
+                weights: vec![("a".to_string(), 1), ("b".to_string(), 2)]
+                    .into_iter()
+                    .collect(),
+                offset: (0.5, 1.5),
+                team: Team::Blue,
+            })
+        );
+        assert_eq!(entity.get::<Speed>(), Some(&Speed(2.5)));
+    }
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Stats {
+        hitpoints: f32,
+        level: u8,
+        gold: u64,
+    }
+
+    #[derive(Component, Debug, Deserialize, PartialEq)]
+    struct Ammo {
+        count: u16,
+        spread: f64,
+    }
+
+    #[test]
+    fn test_numeric_coercion() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Stats>()
+            .register_blueprint_component_deserializer::<Ammo>("Ammo");
+
+        let insert = |world: &mut World, source: &str| {
+            let bp = Blueprint::load_from_ron_bytes(Path::new("stats.bp.ron"), source.as_bytes())
+                .unwrap();
+            let bp_handle = world.resource_mut::<Assets<Blueprint>>().add(bp);
+            let ent = world.spawn().id();
+
+            InsertBlueprintCommand {
+                entity: ent,
+                blueprint: bp_handle,
+                seed: None,
+                params: Default::default(),
+            }
+            .write(world);
+
+            let mut events = world.resource_mut::<Events<BlueprintSpawnError>>();
+            let errors: Vec<String> = events
+                .drain()
+                .map(|error| error.error.to_string())
+                .collect();
+
+            (ent, errors)
+        };
+
+        let (ent, errors) = insert(
+            &mut app.world,
+            r#"
+            {
+                "Stats": (hitpoints: 150, level: 3, gold: 12.0),
+                "Ammo": (count: 30, spread: 2),
+            }
+            "#,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            app.world.entity(ent).get::<Stats>(),
+            Some(&Stats {
+                hitpoints: 150.0,
+                level: 3,
+                gold: 12
+            })
+        );
+        assert_eq!(
+            app.world.entity(ent).get::<Ammo>(),
+            Some(&Ammo {
+                count: 30,
+                spread: 2.0
+            })
+        );
+
+        let (ent, errors) = insert(
+            &mut app.world,
+            r#"
+            {
+                "Stats": (level: 300),
+                "Ammo": (count: -1, spread: 2),
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&"300 is out of range for u8".to_string()));
+        assert!(errors
+            .iter()
+            .any(|error| error.contains("-1 is out of range for u16")));
+        assert!(!app.world.entity(ent).contains::<Stats>());
+        assert!(!app.world.entity(ent).contains::<Ammo>());
+
+        let (_, errors) = insert(&mut app.world, r#"{ "Stats": (level: 1.5) }"#);
+        assert_eq!(errors, vec!["1.5 is out of range for u8".to_string()]);
+    }
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Save {
+        enabled: bool,
+        id: u64,
+        scale: f64,
+        limit: Option<f32>,
+    }
+
+    #[derive(Component, Debug, Deserialize, PartialEq)]
+    struct Label {
+        text: Option<String>,
+        hidden: bool,
+        color: Option<(f32, f32, f32)>,
+    }
+
+    #[test]
+    fn test_spawn_value_types() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Save>()
+            .register_type::<Option<f32>>()
+            .register_type_data::<Option<f32>, ReflectDeserialize>()
+            .register_blueprint_component_deserializer::<Label>("Label");
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("save.bp.ron"),
+            br#"
+            {
+                "Save": (enabled: true, id: 18446744073709551615, scale: 0.1, limit: Some(2.5)),
+                "Label": (text: None, hidden: true, color: (1.0, 0.5, 0.0)),
+            }
+            "#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        assert_eq!(ManualEventReader::default().iter(events).count(), 0);
+
+        let entity = app.world.entity(ent);
+        assert_eq!(
+            entity.get::<Save>(),
+            Some(&Save {
+                enabled: true,
+                id: u64::MAX,
+                scale: 0.1,
+                limit: Some(2.5),
+            })
+        );
+        assert_eq!(
+            entity.get::<Label>(),
+            Some(&Label {
+                text: None,
+                hidden: true,
+                color: Some((1.0, 0.5, 0.0)),
+            })
+        );
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    enum Attack {
+        Bark,
+        Bite(u8),
+        Claw(f32, f32),
+        Breath { range: f32 },
+    }
+
+    #[derive(Component, Debug, Deserialize, Serialize, PartialEq)]
+    struct Attacks(Vec<Attack>);
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Guard {
+        team: Team,
+        post: Vec3,
+    }
+
+    #[test]
+    fn test_spawn_enums() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Transform>()
+            .register_type::<Guard>()
+            .register_type::<Team>()
+            .register_blueprint_component_deserializer::<Attacks>("Attacks");
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("enums.bp.ron"),
+            br#"
+            {
+                "Transform": Transform(translation: Vec3(x: 1.0, y: 2.0, z: 3.0)),
+                "Guard": Guard(team: Blue, post: (0.0, 1.0, 0.0)),
+                "Attacks": Attacks([Bark, Bite(3), Claw(1.0, 2.0), Breath(range: 4.5)]),
+            }
+            "#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        assert_eq!(ManualEventReader::default().iter(events).count(), 0);
+
+        let entity = app.world.entity(ent);
+        assert_eq!(
+            entity.get::<Transform>(),
+            Some(&Transform::from_xyz(1.0, 2.0, 3.0))
+        );
+        assert_eq!(
+            entity.get::<Guard>(),
+            Some(&Guard {
+                team: Team::Blue,
+                post: Vec3::Y,
+            })
+        );
+        assert_eq!(
+            entity.get::<Attacks>(),
+            Some(&Attacks(vec![
+                Attack::Bark,
+                Attack::Bite(3),
+                Attack::Claw(1.0, 2.0),
+                Attack::Breath { range: 4.5 },
+            ]))
+        );
+    }
+
+    /// Records the order components are added in
+    #[derive(Default)]
+    struct AddedComponents(Vec<String>);
+
+    struct RecordingAdder;
+
+    impl ComponentAdder for RecordingAdder {
+        fn add_to_entity(
+            &self,
+            world: &mut World,
+            _entity: Entity,
+            component_name: String,
+            _component_value: Value,
+        ) -> Result<(), Error> {
+            world
+                .get_resource_or_insert_with(AddedComponents::default)
+                .0
+                .push(component_name);
+            Ok(())
+        }
+
+        fn remove_from_entity(&self, _world: &mut World, _entity: Entity) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_spawn_component_order() {
+        let names = ["Zeta", "Alpha", "Mid", "Beta", "Aardvark"];
+
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin);
+        for name in names {
+            app.register_blueprint_component(name, RecordingAdder);
+        }
+
+        let bp = Blueprint::load_from_ron_bytes(
+            Path::new("ordered.bp.ron"),
+            br#"{ "Zeta": 1, "Alpha": 2, "Mid": 3, "Beta": 4, "Aardvark": 5 }"#,
+        )
+        .unwrap();
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        for _ in 0..8 {
+            let ent = app.world.spawn().id();
+
+            InsertBlueprintCommand {
+                entity: ent,
+                blueprint: bp_handle.clone(),
+                seed: None,
+                params: Default::default(),
+            }
+            .write(&mut app.world);
+
+            let added = app.world.remove_resource::<AddedComponents>().unwrap();
+            assert_eq!(added.0, names);
+        }
+    }
+
+    #[test]
+    fn test_capture_blueprint() {
+        let mut app = App::new();
+
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(PrintsPlugin)
+            .register_type::<Transform>()
+            .register_type::<Guard>()
+            .register_type::<Team>()
+            .register_type::<Patrol>()
+            .register_type::<Vec<Vec3>>()
+            .register_type::<HashMap<String, i32>>()
+            .register_type::<(f32, f32)>()
+            .register_type::<Speed>()
+            .register_type::<TestComp>()
+            .register_blueprint_component_serde::<Attacks>("Attacks");
+
+        let patrol = || Patrol {
+            points: vec![Vec3::X, Vec3::Z],
+            weights: vec![("b".to_string(), 2), ("a".to_string(), 1)]
+                .into_iter()
+                .collect(),
+            offset: (0.5, -0.5),
+            team: Team::Blue,
+        };
+        let attacks = || {
+            Attacks(vec![
+                Attack::Bark,
+                Attack::Bite(3),
+                Attack::Claw(1.0, 2.0),
+                Attack::Breath { range: 4.5 },
+            ])
+        };
+        let transform =
+            Transform::from_xyz(1.0, 2.0, 3.0).with_rotation(Quat::from_rotation_y(0.5));
+
+        let grandchild = app.world.spawn().insert(TestComp { x: 2.0, y: 2.0 }).id();
+        let child = app
+            .world
+            .spawn()
+            .insert(TestComp { x: 1.0, y: 1.0 })
+            .push_children(&[grandchild])
+            .id();
+        let captured = app
+            .world
+            .spawn()
+            .insert(transform)
+            .insert(Guard {
+                team: Team::Blue,
+                post: Vec3::Y,
+            })
+            .insert(patrol())
+            .insert(Speed(7.5))
+            .insert(attacks())
+            .insert(RuntimeMarker)
+            .push_children(&[child])
+            .id();
+
+        let without_children = capture_blueprint(&app.world, captured, "lonely", false).unwrap();
+        assert_eq!(without_children.entity.children().count(), 0);
+
+        let blueprint = capture_blueprint(&app.world, captured, "captured", true).unwrap();
+        let names: Vec<&str> = blueprint
+            .entity
+            .components()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Transform", "Guard", "Patrol", "Speed", "Attacks"]
+        );
+
+        let saved = blueprint.to_ron_string().unwrap();
+        assert!(saved.contains(
+            r#"    "Guard": Guard(
+        team: Blue,
+        post: Vec3(
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        ),
+    ),"#
+        ));
+
+        let bp = Blueprint::load_from_ron_bytes(Path::new("captured.bp.ron"), saved.as_bytes())
+            .unwrap_or_else(|e| panic!("{}\n{}", e, saved));
+        let bp_handle = app.world.resource_mut::<Assets<Blueprint>>().add(bp);
+
+        let ent = app.world.spawn().id();
+
+        InsertBlueprintCommand {
+            entity: ent,
+            blueprint: bp_handle,
+            seed: None,
+            params: Default::default(),
+        }
+        .write(&mut app.world);
+
+        let events = app.world.resource::<Events<BlueprintSpawnError>>();
+        assert_eq!(ManualEventReader::default().iter(events).count(), 0);
+
+        let entity = app.world.entity(ent);
+        assert_eq!(entity.get::<Transform>(), Some(&transform));
+        assert_eq!(
+            entity.get::<Guard>(),
+            Some(&Guard {
+                team: Team::Blue,
+                post: Vec3::Y,
+            })
+        );
+        assert_eq!(entity.get::<Patrol>(), Some(&patrol()));
+        assert_eq!(entity.get::<Speed>(), Some(&Speed(7.5)));
+        assert_eq!(entity.get::<Attacks>(), Some(&attacks()));
+        assert!(entity.get::<RuntimeMarker>().is_none());
+
+        let child = entity.get::<Children>().expect("captured children")[0];
+        assert_eq!(
+            app.world.entity(child).get::<TestComp>(),
+            Some(&TestComp { x: 1.0, y: 1.0 })
+        );
+        let grandchild = app.world.entity(child).get::<Children>().unwrap()[0];
+        assert_eq!(
+            app.world.entity(grandchild).get::<TestComp>(),
+            Some(&TestComp { x: 2.0, y: 2.0 })
+        );
+
+        let mut bare_world = World::new();
+        let bare = bare_world.spawn().id();
+        assert!(matches!(
+            capture_blueprint(&bare_world, bare, "bare", false),
+            Err(Error::MissingTypeRegistry)
+        ));
+    }
 }
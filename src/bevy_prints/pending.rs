@@ -0,0 +1,187 @@
+//! Blueprints inserted before their assets finished loading
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::asset::{AssetServer, Assets, Handle, HandleId, LoadState};
+use bevy::ecs::{
+    entity::Entity,
+    event::Events,
+    prelude::{Component, With},
+    world::World,
+};
+use tracing::warn;
+
+use crate::{
+    bevy_prints::{
+        blueprint_handle,
+        spawn::{send_spawn_errors, ComponentError},
+        InsertBlueprintCommand,
+    },
+    value::Value,
+    Blueprint, Error,
+};
+
+/// Blueprint waiting for its asset, and the blueprints and `$asset`s it
+/// depends on, to load before being inserted on the entity
+#[derive(Component)]
+pub struct PendingBlueprint {
+    pub(crate) blueprint: Handle<Blueprint>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) params: HashMap<String, Value>,
+}
+
+impl PendingBlueprint {
+    pub fn blueprint(&self) -> &Handle<Blueprint> {
+        &self.blueprint
+    }
+}
+
+/// Sent when a blueprint inserted on `entity` could not be loaded
+#[derive(Debug)]
+pub struct BlueprintLoadFailed {
+    pub entity: Entity,
+    pub blueprint: Handle<Blueprint>,
+    /// Asset path of the blueprint that failed to load, the inserted one or
+    /// one it depends on, or of an `$asset` one of them references
+    pub path: Option<String>,
+}
+
+pub(crate) enum BlueprintLoadState {
+    Loading,
+    Loaded,
+    Failed(HandleId),
+}
+
+/// Whether `blueprint`, the blueprints it depends on and the `$asset`s they
+/// reference are loaded
+///
+/// The asset server only loads the `$asset`s of blueprints it loaded, those
+/// of blueprints added to [`Assets<Blueprint>`] directly are not waited on and
+/// are loaded when the blueprint is spawned
+pub(crate) fn blueprint_load_state(
+    world: &World,
+    blueprint: &Handle<Blueprint>,
+) -> Result<BlueprintLoadState, Error> {
+    let blueprints = world
+        .get_resource::<Assets<Blueprint>>()
+        .ok_or(Error::MissingResource("Assets<Blueprint>"))?;
+    let asset_server = world.get_resource::<AssetServer>();
+
+    let mut unvisited = vec![blueprint.id];
+    let mut visited = HashSet::new();
+
+    while let Some(id) = unvisited.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+
+        let load_state = asset_server.map(|asset_server| asset_server.get_load_state(id));
+
+        let blueprint = match blueprints.get(&Handle::weak(id)) {
+            Some(blueprint) => blueprint,
+            None => {
+                return Ok(if load_state == Some(LoadState::Failed) {
+                    BlueprintLoadState::Failed(id)
+                } else {
+                    BlueprintLoadState::Loading
+                });
+            }
+        };
+
+        unvisited.extend(
+            blueprint
+                .dependencies()
+                .map(|path| blueprint_handle(path).id),
+        );
+
+        if let (Some(asset_server), Some(LoadState::Loaded)) = (asset_server, load_state) {
+            for path in blueprint.asset_dependencies() {
+                let asset_id = HandleId::from(path);
+                match asset_server.get_load_state(asset_id) {
+                    // Freed assets are loaded again when the blueprint is
+                    // spawned
+                    LoadState::Loaded | LoadState::Unloaded => {}
+                    LoadState::Failed => return Ok(BlueprintLoadState::Failed(asset_id)),
+                    // The asset server starts loading the dependencies of a
+                    // blueprint after adding it
+                    LoadState::NotLoaded | LoadState::Loading => {
+                        return Ok(BlueprintLoadState::Loading)
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(BlueprintLoadState::Loaded)
+}
+
+pub(crate) fn send_load_failed(
+    world: &mut World,
+    entity: Entity,
+    blueprint: Handle<Blueprint>,
+    failed: HandleId,
+) {
+    let path = world
+        .get_resource::<AssetServer>()
+        .and_then(|asset_server| asset_server.get_handle_path(failed))
+        .map(|path| path.path().to_string_lossy().to_string());
+
+    warn!(?entity, ?path, "Blueprint failed to load");
+
+    if let Some(mut events) = world.get_resource_mut::<Events<BlueprintLoadFailed>>() {
+        events.send(BlueprintLoadFailed {
+            entity,
+            blueprint,
+            path,
+        });
+    }
+}
+
+/// Inserts the [`PendingBlueprint`]s whose assets have loaded
+pub(crate) fn insert_pending_blueprints(world: &mut World) {
+    let pending: Vec<Entity> = world
+        .query_filtered::<Entity, With<PendingBlueprint>>()
+        .iter(world)
+        .collect();
+
+    for entity in pending {
+        let state = {
+            let pending = world.get::<PendingBlueprint>(entity).unwrap();
+            blueprint_load_state(world, &pending.blueprint)
+        };
+
+        match state {
+            Ok(BlueprintLoadState::Loading) => {}
+            Ok(BlueprintLoadState::Loaded) => {
+                let pending = take_pending(world, entity);
+                InsertBlueprintCommand {
+                    entity,
+                    blueprint: pending.blueprint,
+                    seed: pending.seed,
+                    params: pending.params,
+                }
+                .insert(world);
+            }
+            Ok(BlueprintLoadState::Failed(failed)) => {
+                let pending = take_pending(world, entity);
+                send_load_failed(world, entity, pending.blueprint, failed);
+            }
+            Err(error) => {
+                let pending = take_pending(world, entity);
+                let error = ComponentError {
+                    entity,
+                    component: None,
+                    error,
+                };
+                send_spawn_errors(world, &pending.blueprint, vec![error]);
+            }
+        }
+    }
+}
+
+fn take_pending(world: &mut World, entity: Entity) -> PendingBlueprint {
+    world
+        .entity_mut(entity)
+        .remove::<PendingBlueprint>()
+        .unwrap()
+}
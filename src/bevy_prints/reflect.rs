@@ -0,0 +1,433 @@
+//! Building reflected component values from blueprint [`Value`]s, and
+//! [`Value`]s from reflected components
+
+use std::any::TypeId;
+
+use bevy::asset::{AssetServer, Handle, HandleId};
+use bevy::reflect::{
+    DynamicArray, DynamicList, DynamicMap, DynamicStruct, DynamicTuple, DynamicTupleStruct, Map,
+    Reflect, ReflectDeserialize, ReflectRef, ReflectSerialize, Struct, TypeInfo,
+    TypeRegistryInternal, ValueInfo,
+};
+use indexmap::IndexMap;
+
+use crate::{
+    bevy_prints::blueprint_handle,
+    value::{FromNumber, Value},
+    Blueprint, Error,
+};
+
+/// Reflected [`Handle`] of any asset type, it applies to a `Handle<T>` field
+/// whatever `T` is
+///
+/// [`Handle`]: bevy::asset::Handle
+fn asset_handle(path: &str) -> DynamicStruct {
+    let mut handle = DynamicStruct::default();
+    handle.insert("id", HandleId::from(path));
+    handle
+}
+
+fn unsupported(value: &Value) -> Error {
+    Error::UnexpectedType {
+        unexpected: value.typename(),
+        expected: "a value supported by reflection",
+    }
+}
+
+fn unexpected(value: &Value, type_info: &TypeInfo) -> Error {
+    Error::UnexpectedType {
+        unexpected: value.typename(),
+        expected: type_info.type_name(),
+    }
+}
+
+/// Builds the reflected form of `value` for a type described by `type_info`.
+/// Structs, tuple structs, tuples, lists, arrays and maps are built
+/// recursively from the type info of their fields and items, named structs
+/// like `Transform(...)` having the name of their type. Any other type,
+/// including enums and types like `Quat` that are reflected as opaque
+/// values, is deserialized with its registered [`ReflectDeserialize`].
+///
+/// Fields and items of types that are not registered in `type_registry`
+/// fail with [`Error::UnregisteredType`]
+pub(crate) fn build_reflect(
+    type_registry: &TypeRegistryInternal,
+    type_info: &TypeInfo,
+    value: &Value,
+) -> Result<Box<dyn Reflect>, Error> {
+    let field_info = |type_id, type_name: &str| {
+        type_registry
+            .get_type_info(type_id)
+            .ok_or_else(|| Error::UnregisteredType(type_name.to_string()))
+    };
+
+    let reflected: Box<dyn Reflect> = match (type_info, value) {
+        // Enums are deserialized as values, the name of a struct has to be
+        // the name of its type
+        (TypeInfo::Struct(_) | TypeInfo::TupleStruct(_), Value::Named(name, content)) => {
+            let expected =
+                short_type_name(type_registry, type_info.type_id(), type_info.type_name());
+            if *name != expected {
+                return Err(Error::UnexpectedName {
+                    name: name.clone(),
+                    expected,
+                });
+            }
+
+            build_reflect(type_registry, type_info, content)?
+        }
+        (TypeInfo::Struct(_), Value::Blueprint(path)) => Box::new(blueprint_handle(path)),
+        (TypeInfo::Struct(_), Value::Asset(path)) => {
+            let mut handle = asset_handle(path);
+            handle.set_name(type_info.type_name().to_string());
+            Box::new(handle)
+        }
+        (TypeInfo::Struct(info), Value::KeyMap(fields)) => {
+            let mut reflected = DynamicStruct::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for (name, value) in fields {
+                let field = info.field(name).ok_or_else(|| Error::UnknownField {
+                    type_name: info.type_name(),
+                    field: name.clone(),
+                })?;
+                let field_value = build_reflect(
+                    type_registry,
+                    field_info(field.type_id(), field.type_name())?,
+                    value,
+                )?;
+                reflected.insert_boxed(name, field_value);
+            }
+
+            Box::new(reflected)
+        }
+        (TypeInfo::Struct(info), Value::Unit) if info.field_len() == 0 => {
+            let mut reflected = DynamicStruct::default();
+            reflected.set_name(info.type_name().to_string());
+            Box::new(reflected)
+        }
+        // Fields in declaration order, like `(1.0, 2.0, 3.0)` for a `Vec3`
+        (TypeInfo::Struct(info), Value::Vec(values)) => {
+            let mut reflected = DynamicStruct::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for (index, value) in values.iter().enumerate() {
+                let field = info.field_at(index).ok_or_else(|| Error::UnknownField {
+                    type_name: info.type_name(),
+                    field: index.to_string(),
+                })?;
+                let field_value = build_reflect(
+                    type_registry,
+                    field_info(field.type_id(), field.type_name())?,
+                    value,
+                )?;
+                reflected.insert_boxed(field.name(), field_value);
+            }
+
+            Box::new(reflected)
+        }
+        (TypeInfo::TupleStruct(info), Value::Vec(values)) => {
+            let mut reflected = DynamicTupleStruct::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for (index, value) in values.iter().enumerate() {
+                let field = info.field_at(index).ok_or_else(|| Error::UnknownField {
+                    type_name: info.type_name(),
+                    field: index.to_string(),
+                })?;
+                reflected.insert_boxed(build_reflect(
+                    type_registry,
+                    field_info(field.type_id(), field.type_name())?,
+                    value,
+                )?);
+            }
+
+            Box::new(reflected)
+        }
+        // Newtype given its inner value directly
+        (TypeInfo::TupleStruct(info), value) if info.field_len() == 1 => {
+            let field = info.field_at(0).unwrap();
+
+            let mut reflected = DynamicTupleStruct::default();
+            reflected.set_name(info.type_name().to_string());
+            reflected.insert_boxed(build_reflect(
+                type_registry,
+                field_info(field.type_id(), field.type_name())?,
+                value,
+            )?);
+
+            Box::new(reflected)
+        }
+        (TypeInfo::Tuple(info), Value::Vec(values)) => {
+            let mut reflected = DynamicTuple::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for (index, value) in values.iter().enumerate() {
+                let field = info.field_at(index).ok_or_else(|| Error::UnknownField {
+                    type_name: info.type_name(),
+                    field: index.to_string(),
+                })?;
+                reflected.insert_boxed(build_reflect(
+                    type_registry,
+                    field_info(field.type_id(), field.type_name())?,
+                    value,
+                )?);
+            }
+
+            Box::new(reflected)
+        }
+        (TypeInfo::List(info), Value::Vec(values)) => {
+            let mut reflected = DynamicList::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for value in values {
+                reflected.push_box(build_reflect(
+                    type_registry,
+                    field_info(info.item_type_id(), info.item_type_name())?,
+                    value,
+                )?);
+            }
+
+            Box::new(reflected)
+        }
+        (TypeInfo::Array(info), Value::Vec(values)) if values.len() == info.capacity() => {
+            let items = values
+                .iter()
+                .map(|value| {
+                    build_reflect(
+                        type_registry,
+                        field_info(info.item_type_id(), info.item_type_name())?,
+                        value,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut reflected = DynamicArray::new(items.into_boxed_slice());
+            reflected.set_name(info.type_name().to_string());
+
+            Box::new(reflected)
+        }
+        (TypeInfo::Map(info), Value::KeyMap(entries)) => {
+            let mut reflected = DynamicMap::default();
+            reflected.set_name(info.type_name().to_string());
+
+            for (key, value) in entries {
+                let key = build_reflect(
+                    type_registry,
+                    field_info(info.key_type_id(), info.key_type_name())?,
+                    &Value::String(key.clone()),
+                )?;
+                let value = build_reflect(
+                    type_registry,
+                    field_info(info.value_type_id(), info.value_type_name())?,
+                    value,
+                )?;
+                reflected.insert_boxed(key, value);
+            }
+
+            Box::new(reflected)
+        }
+        (TypeInfo::Value(info), value) => match value {
+            Value::String(s) if info.is::<String>() => Box::new(s.clone()),
+            Value::Bool(v) if info.is::<bool>() => Box::new(*v),
+            value if value.is_number() && is_number_type(info) => build_number(info, value)?,
+            Value::Entity(_) => return Err(unsupported(value)),
+            _ => {
+                let deserialize = type_registry
+                    .get_type_data::<ReflectDeserialize>(info.type_id())
+                    .ok_or_else(|| unexpected(value, type_info))?;
+
+                deserialize.deserialize(value.deserializer())?
+            }
+        },
+        (TypeInfo::Dynamic(info), _) => {
+            return Err(Error::UnregisteredType(info.type_name().to_string()))
+        }
+        (_, value) => return Err(unexpected(value, type_info)),
+    };
+
+    Ok(reflected)
+}
+
+macro_rules! number_types {
+    ($callback:ident) => {
+        $callback!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64)
+    };
+}
+
+fn is_number_type(info: &ValueInfo) -> bool {
+    macro_rules! is_any {
+        ($($ty:ty),*) => {
+            $(info.is::<$ty>())||*
+        };
+    }
+
+    number_types!(is_any)
+}
+
+/// Converts the number `value` to the numeric type described by `info`
+fn build_number(info: &ValueInfo, value: &Value) -> Result<Box<dyn Reflect>, Error> {
+    fn convert<T: FromNumber + Reflect>(value: &Value) -> Result<Box<dyn Reflect>, Error> {
+        match value.to_number::<T>() {
+            Some(v) => Ok(Box::new(v)),
+            None => Err(Error::NumberOutOfRange {
+                value: value.number_string(),
+                target: T::NAME,
+            }),
+        }
+    }
+
+    macro_rules! convert_to {
+        ($($ty:ty),*) => {
+            $(
+                if info.is::<$ty>() {
+                    return convert::<$ty>(value);
+                }
+            )*
+        };
+    }
+
+    number_types!(convert_to);
+
+    unreachable!("{} is not a number type", info.type_name())
+}
+
+/// Name of a type as written in blueprints, like `Transform` for
+/// `bevy_transform::components::transform::Transform`
+fn short_type_name(
+    type_registry: &TypeRegistryInternal,
+    type_id: TypeId,
+    type_name: &str,
+) -> String {
+    match type_registry.get(type_id) {
+        Some(registration) => registration.short_name().to_string(),
+        None => {
+            let path = type_name.split('<').next().unwrap_or(type_name);
+            path.rsplit("::").next().unwrap_or(path).to_string()
+        }
+    }
+}
+
+/// Id of a reflected [`Handle`] of any asset type, a struct whose `id` field
+/// is a [`HandleId`]
+fn handle_id(reflected: &dyn Struct) -> Option<HandleId> {
+    reflected
+        .field("id")
+        .and_then(|id| id.downcast_ref::<HandleId>())
+        .copied()
+}
+
+/// `$asset` or `$blueprint` value for a reflected [`Handle`] with id `id`,
+/// from the path the asset server loaded it from
+fn handle_value(
+    asset_server: Option<&AssetServer>,
+    handle: &dyn Struct,
+    id: HandleId,
+) -> Result<Value, Error> {
+    let asset_path = asset_server
+        .ok_or(Error::MissingAssetServer)?
+        .get_handle_path(id)
+        .ok_or_else(|| Error::UnknownAssetPath(handle.type_name().to_string()))?;
+
+    let path = asset_path.path().to_string_lossy();
+    let path = match asset_path.label() {
+        Some(label) => format!("{}#{}", path, label),
+        None => path.to_string(),
+    };
+
+    if handle.as_any().is::<Handle<Blueprint>>() {
+        Ok(Value::Blueprint(path))
+    } else {
+        Ok(Value::Asset(path))
+    }
+}
+
+/// Converts a reflected component back into the [`Value`] that builds it,
+/// the counterpart of [`build_reflect`]. Structs and tuple structs become
+/// named values, handles their asset path, and types reflected as opaque
+/// values are serialized with serde
+pub(crate) fn value_from_reflect(
+    type_registry: &TypeRegistryInternal,
+    asset_server: Option<&AssetServer>,
+    reflected: &dyn Reflect,
+) -> Result<Value, Error> {
+    let from_reflect = |reflected| value_from_reflect(type_registry, asset_server, reflected);
+
+    let value = match reflected.reflect_ref() {
+        ReflectRef::Struct(reflected_struct) => {
+            if let Some(id) = handle_id(reflected_struct) {
+                return handle_value(asset_server, reflected_struct, id);
+            }
+
+            let fields = (0..reflected_struct.field_len())
+                .map(|index| {
+                    let name = reflected_struct.name_at(index).unwrap().to_string();
+                    let field = reflected_struct.field_at(index).unwrap();
+                    Ok((name, from_reflect(field)?))
+                })
+                .collect::<Result<IndexMap<_, _>, Error>>()?;
+
+            Value::Named(
+                short_type_name(
+                    type_registry,
+                    reflected.as_any().type_id(),
+                    reflected.type_name(),
+                ),
+                Box::new(Value::KeyMap(fields)),
+            )
+        }
+        ReflectRef::TupleStruct(tuple_struct) => {
+            let fields = tuple_struct
+                .iter_fields()
+                .map(from_reflect)
+                .collect::<Result<_, _>>()?;
+
+            Value::Named(
+                short_type_name(
+                    type_registry,
+                    reflected.as_any().type_id(),
+                    reflected.type_name(),
+                ),
+                Box::new(Value::Vec(fields)),
+            )
+        }
+        ReflectRef::Tuple(tuple) => Value::Vec(
+            tuple
+                .iter_fields()
+                .map(from_reflect)
+                .collect::<Result<_, _>>()?,
+        ),
+        ReflectRef::List(list) => {
+            Value::Vec(list.iter().map(from_reflect).collect::<Result<_, _>>()?)
+        }
+        ReflectRef::Array(array) => {
+            Value::Vec(array.iter().map(from_reflect).collect::<Result<_, _>>()?)
+        }
+        ReflectRef::Map(map) => {
+            let mut entries = map
+                .iter()
+                .map(|(key, value)| match from_reflect(key)? {
+                    Value::String(key) => Ok((key, from_reflect(value)?)),
+                    key => Err(Error::UnexpectedType {
+                        unexpected: key.typename(),
+                        expected: "a string map key",
+                    }),
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            // Hash maps have no order of their own, sorted so saving the same
+            // map twice gives the same blueprint
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            Value::KeyMap(entries.into_iter().collect())
+        }
+        ReflectRef::Value(reflected) => {
+            let serialize = type_registry
+                .get_type_data::<ReflectSerialize>(reflected.as_any().type_id())
+                .ok_or_else(|| Error::NotSerializable(reflected.type_name().to_string()))?;
+
+            Value::from_component(serialize.get_serializable(reflected).borrow())?
+        }
+    };
+
+    Ok(value)
+}
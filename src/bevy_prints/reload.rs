@@ -0,0 +1,203 @@
+//! Re-applying blueprints to the entities built from them when they change
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::asset::{AssetEvent, Assets, Handle, HandleId};
+use bevy::ecs::{
+    entity::Entity,
+    event::EventReader,
+    prelude::{Component, Query, Res, World},
+    system::{Command, Commands},
+};
+use bevy::hierarchy::DespawnRecursiveExt;
+use tracing::{debug, info};
+
+use crate::{
+    bevy_prints::{
+        blueprint_handle, eval_blueprint,
+        pending::{blueprint_load_state, BlueprintLoadState},
+        spawn::{add_to_entity, send_spawn_errors, ComponentError},
+    },
+    value::Value,
+    Blueprint,
+};
+
+/// Blueprint an entity was built from, kept to re-apply it when the blueprint
+/// or one it depends on is modified
+#[derive(Component, Clone)]
+pub struct BlueprintInstance {
+    blueprint: Handle<Blueprint>,
+    /// Seed used for the random functions, reused so re-applying only
+    /// changes what was edited
+    seed: u64,
+    params: HashMap<String, Value>,
+    /// Components the blueprint defined, the ones a new version no longer
+    /// defines are removed
+    components: Vec<String>,
+    /// Children spawned by the blueprint, replaced when it is re-applied
+    children: Vec<Entity>,
+}
+
+impl BlueprintInstance {
+    pub fn blueprint(&self) -> &Handle<Blueprint> {
+        &self.blueprint
+    }
+}
+
+/// Evaluates `blueprint` and adds it to `entity`, recording it in a
+/// [`BlueprintInstance`]. With the `previous` instance the components it no
+/// longer defines are removed. Children spawned by the `previous` instance,
+/// or by a blueprint inserted on `entity` before, are despawned
+pub(crate) fn apply_blueprint(
+    world: &mut World,
+    entity: Entity,
+    blueprint: Handle<Blueprint>,
+    seed: u64,
+    params: HashMap<String, Value>,
+    previous: Option<BlueprintInstance>,
+) {
+    let mut entity_value =
+        match eval_blueprint(world, blueprint.clone(), Some(seed), params.clone()) {
+            Ok(entity_value) => entity_value,
+            Err(error) => {
+                let error = ComponentError {
+                    entity,
+                    component: None,
+                    error,
+                };
+                send_spawn_errors(world, &blueprint, vec![error]);
+                return;
+            }
+        };
+    debug!(data=?&entity_value, "Blueprint data");
+
+    let components: Vec<String> = entity_value
+        .components()
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    let previous_children = match previous {
+        Some(previous) => {
+            for name in previous.components {
+                if entity_value.get_component(&name).is_none() {
+                    entity_value.remove_component(&name);
+                }
+            }
+            previous.children
+        }
+        None => world
+            .get::<BlueprintInstance>(entity)
+            .map(|inserted| inserted.children.clone())
+            .unwrap_or_default(),
+    };
+
+    for child in previous_children {
+        if world.get_entity(child).is_some() {
+            world.entity_mut(child).despawn_recursive();
+        }
+    }
+
+    let mut errors = Vec::new();
+    let children = add_to_entity(world, entity, entity_value, &mut errors);
+
+    // A despawned entity is among the errors from `add_to_entity`
+    if let Some(mut ent_mut) = world.get_entity_mut(entity) {
+        ent_mut.insert(BlueprintInstance {
+            blueprint: blueprint.clone(),
+            seed,
+            params,
+            components,
+            children,
+        });
+    }
+
+    send_spawn_errors(world, &blueprint, errors);
+}
+
+/// Whether `blueprint` is, or depends on, one of the blueprints in `modified`
+fn is_affected(
+    blueprints: &Assets<Blueprint>,
+    blueprint: &Handle<Blueprint>,
+    modified: &HashSet<HandleId>,
+) -> bool {
+    let mut unvisited = vec![blueprint.id];
+    let mut visited = HashSet::new();
+
+    while let Some(id) = unvisited.pop() {
+        if modified.contains(&id) {
+            return true;
+        }
+
+        if !visited.insert(id) {
+            continue;
+        }
+
+        if let Some(blueprint) = blueprints.get(&Handle::weak(id)) {
+            unvisited.extend(
+                blueprint
+                    .dependencies()
+                    .map(|path| blueprint_handle(path).id),
+            );
+        }
+    }
+
+    false
+}
+
+/// Queues re-applying the blueprints of the entities built from a modified
+/// blueprint
+pub(crate) fn reload_modified_blueprints(
+    mut events: EventReader<AssetEvent<Blueprint>>,
+    blueprints: Res<Assets<Blueprint>>,
+    instances: Query<(Entity, &BlueprintInstance)>,
+    mut commands: Commands,
+) {
+    let modified: HashSet<HandleId> = events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.id),
+            _ => None,
+        })
+        .collect();
+
+    if modified.is_empty() {
+        return;
+    }
+
+    for (entity, instance) in instances.iter() {
+        if is_affected(&blueprints, &instance.blueprint, &modified) {
+            commands.add(ReloadBlueprintCommand { entity });
+        }
+    }
+}
+
+struct ReloadBlueprintCommand {
+    entity: Entity,
+}
+
+impl Command for ReloadBlueprintCommand {
+    fn write(self, world: &mut World) {
+        let instance = match world
+            .get_entity(self.entity)
+            .and_then(|entity| entity.get::<BlueprintInstance>())
+        {
+            Some(instance) => instance.clone(),
+            None => return,
+        };
+
+        // Wait for the next modification when the new version references
+        // blueprints that are not loaded yet
+        if let Ok(BlueprintLoadState::Loaded) = blueprint_load_state(world, &instance.blueprint) {
+            info!(entity=?self.entity, "Blueprint reload");
+
+            apply_blueprint(
+                world,
+                self.entity,
+                instance.blueprint.clone(),
+                instance.seed,
+                instance.params.clone(),
+                Some(instance),
+            );
+        }
+    }
+}
@@ -1,89 +1,267 @@
+use bevy::asset::{AssetServer, Handle};
 use bevy::ecs::{
     entity::Entity,
+    event::Events,
     prelude::{Mut, ReflectComponent, World},
 };
-use bevy::reflect::{
-    DynamicStruct, DynamicTupleStruct, Reflect, TupleStruct, TypeRegistryInternal,
-};
+use bevy::hierarchy::{BuildWorldChildren, DespawnRecursiveExt};
+use bevy::reflect::{TypeRegistration, TypeRegistryArc, TypeRegistryInternal};
+use tracing::warn;
 
 use crate::{
-    bevy_prints::BlueprintComponentAdderRegistry,
+    bevy_prints::{reflect::build_reflect, BlueprintAssets, BlueprintComponentAdderRegistry},
     value::{EntityMap, Value},
+    Blueprint, Error,
 };
 
+/// Asset paths referenced by `value` with `$asset`
+fn collect_asset_paths<'v>(value: &'v Value, paths: &mut Vec<&'v str>) {
+    match value {
+        Value::Asset(path) => paths.push(path),
+        Value::KeyMap(values) => {
+            for value in values.values() {
+                collect_asset_paths(value, paths);
+            }
+        }
+        Value::Vec(values) => {
+            for value in values {
+                collect_asset_paths(value, paths);
+            }
+        }
+        Value::Entity(entity) => {
+            for (_, value) in entity.components() {
+                collect_asset_paths(value, paths);
+            }
+        }
+        Value::Option(Some(value)) | Value::Named(_, value) => collect_asset_paths(value, paths),
+        Value::String(_)
+        | Value::Bool(_)
+        | Value::I32(_)
+        | Value::I64(_)
+        | Value::U64(_)
+        | Value::F32(_)
+        | Value::F64(_)
+        | Value::Unit
+        | Value::Option(None)
+        | Value::Blueprint(_) => {}
+    }
+}
+
+/// Loads the assets the components of `entity_value` reference and keeps
+/// them alive in [`BlueprintAssets`] so the weak handles in the components
+/// stay valid
+fn load_assets(
+    world: &mut World,
+    entity: Entity,
+    entity_value: &EntityMap<Value>,
+) -> Result<(), Error> {
+    let mut paths = Vec::new();
+    for (_, value) in entity_value.components() {
+        collect_asset_paths(value, &mut paths);
+    }
+
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let asset_server = world
+        .get_resource::<AssetServer>()
+        .ok_or(Error::MissingAssetServer)?
+        .clone();
+
+    let handles = paths
+        .into_iter()
+        .map(|path| asset_server.load_untyped(path));
+
+    let mut ent_mut = world
+        .get_entity_mut(entity)
+        .ok_or(Error::NoSuchEntity(entity))?;
+    match ent_mut.get_mut::<BlueprintAssets>() {
+        Some(mut assets) => assets.extend(handles),
+        None => {
+            let mut assets = BlueprintAssets::default();
+            assets.extend(handles);
+            ent_mut.insert(assets);
+        }
+    }
+
+    Ok(())
+}
+
+fn component_registration<'r>(
+    type_registry: &'r TypeRegistryInternal,
+    component_name: &str,
+) -> Result<(&'r TypeRegistration, &'r ReflectComponent), Error> {
+    let registration = type_registry
+        .get_with_short_name(component_name)
+        .ok_or_else(|| Error::UnknownComponent(component_name.to_string()))?;
+    let reflect_component = registration
+        .data::<ReflectComponent>()
+        .ok_or_else(|| Error::NotAComponent(component_name.to_string()))?;
+
+    Ok((registration, reflect_component))
+}
+
+fn remove_component_with_reflect(
+    type_registry: &TypeRegistryInternal,
+    world: &mut World,
+    entity: Entity,
+    component_name: &str,
+) -> Result<(), Error> {
+    let (_, reflect_component) = component_registration(type_registry, component_name)?;
+    reflect_component.remove(world, entity);
+    Ok(())
+}
+
 fn add_component_with_reflect(
     type_registry: &TypeRegistryInternal,
     world: &mut World,
     entity: Entity,
     component_name: String,
     component_value: Value,
+) -> Result<(), Error> {
+    let (registration, reflect_component) = component_registration(type_registry, &component_name)?;
+    let component = build_reflect(type_registry, registration.type_info(), &component_value)?;
+
+    reflect_component.apply_or_insert(world, entity, &*component);
+
+    Ok(())
+}
+
+/// Sent when part of a blueprint could not be added to `entity`, the rest of
+/// the blueprint is still added
+#[derive(Debug)]
+pub struct BlueprintSpawnError {
+    /// Entity the blueprint was inserted on, or one of the children it spawned
+    pub entity: Entity,
+    pub blueprint: Handle<Blueprint>,
+    /// Component that failed, `None` when the blueprint as a whole failed
+    pub component: Option<String>,
+    pub error: Error,
+}
+
+/// Component that could not be added to, or removed from, an entity
+#[derive(Debug)]
+pub(crate) struct ComponentError {
+    pub entity: Entity,
+    /// `None` when the entity as a whole failed
+    pub component: Option<String>,
+    pub error: Error,
+}
+
+pub(crate) fn send_spawn_errors(
+    world: &mut World,
+    blueprint: &Handle<Blueprint>,
+    errors: Vec<ComponentError>,
 ) {
-    let registration = match type_registry.get_with_short_name(&component_name) {
-        Some(registration) => registration,
-        None => {
-            panic!(
-                "Could not find component named `{}` in the type registry.",
-                component_name
-            );
-        }
-    };
+    for ComponentError {
+        entity,
+        component,
+        error,
+    } in errors
+    {
+        warn!(?entity, ?component, %error, "Blueprint spawn error");
 
-    let reflect_component = registration.data::<ReflectComponent>().unwrap();
+        if let Some(mut events) = world.get_resource_mut::<Events<BlueprintSpawnError>>() {
+            events.send(BlueprintSpawnError {
+                entity,
+                blueprint: blueprint.clone(),
+                component,
+                error,
+            });
+        }
+    }
+}
 
-    let component: Box<dyn Reflect> = match component_value {
-        Value::KeyMap(fields) => {
-            let mut component = Box::new(DynamicStruct::default());
+/// Adds the components of `entity_value` to `entity` and spawns its
+/// children, recursively, attaching them with [`Parent`] and [`Children`].
+/// Components that fail are left out and recorded in `errors` while the rest
+/// are still added. Returns the children spawned for `entity`
+///
+/// [`Parent`]: bevy::hierarchy::Parent
+/// [`Children`]: bevy::hierarchy::Children
+pub(crate) fn add_to_entity(
+    world: &mut World,
+    entity: Entity,
+    mut entity_value: EntityMap<Value>,
+    errors: &mut Vec<ComponentError>,
+) -> Vec<Entity> {
+    // Nothing can be added to an entity that was despawned
+    if world.get_entity(entity).is_none() {
+        errors.push(ComponentError {
+            entity,
+            component: None,
+            error: Error::NoSuchEntity(entity),
+        });
+        return Vec::new();
+    }
 
-            component.set_name(component_name);
+    let children = entity_value.take_children();
 
-            for (key, value) in fields.iter() {
-                //component.field_mut(key).
+    if let Err(error) = load_assets(world, entity, &entity_value) {
+        errors.push(ComponentError {
+            entity,
+            component: None,
+            error,
+        });
+    }
+    add_components(world, entity, entity_value, errors);
 
-                match value {
-                    Value::KeyMap(_) => todo!(),
-                    Value::String(s) => component.insert(key, s.clone()),
-                    Value::I32(v) => component.insert(key, *v),
-                    Value::F32(v) => component.insert(key, *v),
-                    Value::Vec(_v) => todo!(),
-                    Value::Entity(_) => todo!(),
+    children
+        .into_iter()
+        .filter_map(|child_value| {
+            let child_value = match child_value {
+                Value::Entity(child_value) => child_value,
+                unexpected => {
+                    errors.push(ComponentError {
+                        entity,
+                        component: None,
+                        error: Error::UnexpectedType {
+                            unexpected: unexpected.typename(),
+                            expected: "entity",
+                        },
+                    });
+                    return None;
                 }
-            }
+            };
 
-            component
-        }
-        Value::String(_) => todo!(),
-        Value::I32(_) => todo!(),
-        Value::F32(_) => todo!(),
-        Value::Vec(vec) => {
-            let mut component = Box::new(DynamicTupleStruct::default());
-
-            component.set_name(component_name);
-
-            for (field_index, value) in vec.into_iter().enumerate() {
-                match value {
-                    Value::KeyMap(_) => todo!(),
-                    Value::String(s) => component.field_mut(field_index).unwrap().apply(&s), //component.insert(key, s.clone()),
-                    Value::I32(v) => component.field_mut(field_index).unwrap().apply(&v),
-                    Value::F32(v) => component.field_mut(field_index).unwrap().apply(&v),
-                    Value::Vec(_v) => todo!(),
-                    Value::Entity(_) => todo!(),
+            let child = world.spawn().id();
+            add_to_entity(world, child, child_value, errors);
+            match world.get_entity_mut(entity) {
+                Some(mut parent) => {
+                    parent.push_children(&[child]);
+                    Some(child)
+                }
+                None => {
+                    errors.push(ComponentError {
+                        entity,
+                        component: None,
+                        error: Error::NoSuchEntity(entity),
+                    });
+                    world.entity_mut(child).despawn_recursive();
+                    None
                 }
             }
-
-            component
-        }
-        Value::Entity(_) => todo!(),
-    };
-
-    reflect_component.apply_or_insert(world, entity, &*component);
+        })
+        .collect()
 }
 
-pub(crate) fn add_to_entity(world: &mut World, entity: Entity, entity_value: EntityMap<Value>) {
-    let type_registry = {
-        world
-            .get_resource::<bevy::reflect::TypeRegistryArc>()
-            .unwrap()
-            .clone()
+fn add_components(
+    world: &mut World,
+    entity: Entity,
+    entity_value: EntityMap<Value>,
+    errors: &mut Vec<ComponentError>,
+) {
+    let type_registry = match world.get_resource::<TypeRegistryArc>() {
+        Some(type_registry) => type_registry.clone(),
+        None => {
+            errors.push(ComponentError {
+                entity,
+                component: None,
+                error: Error::MissingTypeRegistry,
+            });
+            return;
+        }
     };
 
     let type_registry = type_registry.read();
@@ -92,18 +270,38 @@ pub(crate) fn add_to_entity(world: &mut World, entity: Entity, entity_value: Ent
 
     world.resource_scope(
         |world, adder_registry: Mut<BlueprintComponentAdderRegistry>| {
+            let mut record = |component_name: &str, result: Result<(), Error>| {
+                if let Err(error) = result {
+                    errors.push(ComponentError {
+                        entity,
+                        component: Some(component_name.to_string()),
+                        error,
+                    });
+                }
+            };
+
+            for component_name in entity_value.removed_components() {
+                let result = if let Some(adder) = adder_registry.get_adder(component_name) {
+                    adder.remove_from_entity(world, entity)
+                } else {
+                    remove_component_with_reflect(&type_registry, world, entity, component_name)
+                };
+                record(component_name, result);
+            }
+
             for (component_name, component_value) in entity_value.into_components() {
-                if let Some(adder) = adder_registry.get_adder(&component_name) {
-                    adder.add_to_entity(world, entity, component_name, component_value);
+                let result = if let Some(adder) = adder_registry.get_adder(&component_name) {
+                    adder.add_to_entity(world, entity, component_name.clone(), component_value)
                 } else {
                     add_component_with_reflect(
                         &type_registry,
                         world,
                         entity,
-                        component_name,
+                        component_name.clone(),
                         component_value,
-                    );
-                }
+                    )
+                };
+                record(&component_name, result);
             }
         },
     );
@@ -2,16 +2,32 @@
 
 use crate::{
     value::{EntityMap, Value},
-    Error,
+    Blueprint, Error,
 };
+use indexmap::IndexMap;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 mod entity;
-mod parse;
+pub(crate) mod parse;
+mod ser;
+mod weighted;
+
+pub(crate) use parse::ParsedChildren;
 
 pub(crate) trait Environment {
-    fn eval_func(&self, name: &str, args: &[Value]) -> Result<Value, Error>;
+    fn eval_func(&self, rng: &mut dyn RngCore, name: &str, args: &[Value]) -> Result<Value, Error>;
+}
+
+/// Blueprints that can be referenced by asset path while evaluating
+pub(crate) trait BlueprintLibrary {
+    fn get_blueprint(&self, path: &str) -> Option<&Blueprint>;
 }
 
 pub(crate) trait Evaluatable {
@@ -28,27 +44,255 @@ pub(crate) trait Evaluatable {
     }
 }
 
+/// Named values bound by a `$let`, each evaluated the first time it is referenced
+pub(crate) struct Scope<'a> {
+    bindings: &'a IndexMap<String, Expr>,
+    values: RefCell<HashMap<String, Value>>,
+    evaluating: RefCell<HashSet<String>>,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new(bindings: &'a IndexMap<String, Expr>) -> Self {
+        Scope {
+            bindings,
+            values: RefCell::new(HashMap::new()),
+            evaluating: RefCell::new(HashSet::new()),
+        }
+    }
+}
+
 /// Context for evaluation
 pub(crate) struct Context<'a> {
-    #[allow(dead_code)]
     pub comp_lib: &'a dyn Environment,
+    rng: Rc<RefCell<ChaCha8Rng>>,
+    params: Rc<HashMap<String, Value>>,
+    blueprints: Option<&'a dyn BlueprintLibrary>,
+    /// Asset paths of the blueprints referenced by [`Expr::Blueprint`] being
+    /// evaluated, outermost first
+    including: Rc<RefCell<Vec<String>>>,
+    scope: Option<&'a Scope<'a>>,
+    parent: Option<&'a Context<'a>>,
 }
 
 impl<'a> Context<'a> {
+    /// Context with a randomly chosen seed
     pub fn new(comp_lib: &'a dyn Environment) -> Self {
-        Context { comp_lib }
+        Context::with_seed(comp_lib, rand::random())
+    }
+
+    /// Context whose random functions produce the same values for the same seed
+    pub fn with_seed(comp_lib: &'a dyn Environment, seed: u64) -> Self {
+        Context {
+            comp_lib,
+            rng: Rc::new(RefCell::new(ChaCha8Rng::seed_from_u64(seed))),
+            params: Rc::new(HashMap::new()),
+            blueprints: None,
+            including: Rc::new(RefCell::new(Vec::new())),
+            scope: None,
+            parent: None,
+        }
     }
 
-    pub fn call_function(&self, _name: &str, _args: &[Value]) -> Result<Value, Error> {
-        unimplemented!()
+    /// Blueprints that other blueprints can extend
+    pub fn with_blueprints(mut self, blueprints: &'a dyn BlueprintLibrary) -> Self {
+        self.blueprints = Some(blueprints);
+        self
+    }
+
+    pub fn blueprint(&self, path: &str) -> Result<&'a Blueprint, Error> {
+        self.blueprints
+            .and_then(|blueprints| blueprints.get_blueprint(path))
+            .ok_or_else(|| Error::BlueprintNotLoaded(path.to_string()))
+    }
+
+    /// Entity from the blueprint at `path`, evaluated with its own parameters
+    /// and variables
+    pub fn eval_blueprint(&self, path: &str) -> Result<Value, Error> {
+        let blueprint = self.blueprint(path)?;
+
+        if self
+            .including
+            .borrow()
+            .iter()
+            .any(|including| including == path)
+        {
+            return Err(Error::CyclicBlueprint(path.to_string()));
+        }
+
+        let ctx = Context {
+            comp_lib: self.comp_lib,
+            rng: self.rng.clone(),
+            params: Rc::new(HashMap::new()),
+            blueprints: self.blueprints,
+            including: self.including.clone(),
+            scope: None,
+            parent: None,
+        };
+
+        self.including.borrow_mut().push(path.to_string());
+        let value = blueprint.eval(&ctx);
+        self.including.borrow_mut().pop();
+
+        value
+    }
+
+    /// Supply parameter values referenced with `{"$param": "name"}`
+    pub fn with_params(mut self, params: HashMap<String, Value>) -> Self {
+        self.params = Rc::new(params);
+        self
+    }
+
+    pub fn params(&self) -> &HashMap<String, Value> {
+        &self.params
+    }
+
+    /// Context sharing the environment, random number generator and variables
+    /// but with different parameters
+    pub fn scoped_params(&self, params: HashMap<String, Value>) -> Context<'_> {
+        Context {
+            comp_lib: self.comp_lib,
+            rng: self.rng.clone(),
+            params: Rc::new(params),
+            blueprints: self.blueprints,
+            including: self.including.clone(),
+            scope: self.scope,
+            parent: self.parent,
+        }
+    }
+
+    /// Child context where the variables of `scope` shadow the ones of this context
+    pub fn scoped<'b>(&'b self, scope: &'b Scope<'b>) -> Context<'b> {
+        Context {
+            comp_lib: self.comp_lib,
+            rng: self.rng.clone(),
+            params: self.params.clone(),
+            blueprints: self.blueprints,
+            including: self.including.clone(),
+            scope: Some(scope),
+            parent: Some(self),
+        }
+    }
+
+    pub fn param(&self, name: &str) -> Result<Value, Error> {
+        self.params
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::UndefinedParameter(name.to_string()))
+    }
+
+    /// Value of the innermost variable called `name`
+    pub fn var(&self, name: &str) -> Result<Value, Error> {
+        let mut current = Some(self);
+
+        while let Some(ctx) = current {
+            if let Some(scope) = ctx.scope {
+                if let Some(expr) = scope.bindings.get(name) {
+                    return ctx.eval_binding(scope, name, expr);
+                }
+            }
+            current = ctx.parent;
+        }
+
+        Err(Error::UndefinedVariable(name.to_string()))
+    }
+
+    fn eval_binding(&self, scope: &Scope, name: &str, expr: &Expr) -> Result<Value, Error> {
+        if let Some(value) = scope.values.borrow().get(name) {
+            return Ok(value.clone());
+        }
+
+        if !scope.evaluating.borrow_mut().insert(name.to_string()) {
+            return Err(Error::CyclicVariable(name.to_string()));
+        }
+
+        let value = expr.eval(self);
+        scope.evaluating.borrow_mut().remove(name);
+        let value = value?;
+
+        scope
+            .values
+            .borrow_mut()
+            .insert(name.to_string(), value.clone());
+
+        Ok(value)
+    }
+
+    pub fn call_function(&self, name: &str, args: &[Value]) -> Result<Value, Error> {
+        self.comp_lib
+            .eval_func(&mut *self.rng.borrow_mut(), name, args)
+    }
+
+    /// Picks `count` distinct indices into `weights` using the seeded random number generator
+    pub fn pick_weighted(&self, weights: &[f32], count: usize) -> Result<Vec<usize>, Error> {
+        weighted::pick_weighted(&mut *self.rng.borrow_mut(), weights, count)
+            .map_err(|message| Error::InvalidArguments("weighted".to_string(), message))
     }
 }
 
+/// Asset paths referenced by `$blueprint` and `$asset` expressions
+#[derive(Default)]
+pub(crate) struct AssetRefs<'e> {
+    pub blueprints: Vec<&'e str>,
+    pub assets: Vec<&'e str>,
+}
+
 pub type EntityExpr = EntityMap<Expr>;
 
 impl EntityExpr {
+    /// Components are evaluated in order, so the values drawn from the seeded
+    /// random number generator are the same on every run
     pub(crate) fn eval(&self, ctx: &Context) -> Result<EntityMap<Value>, Error> {
-        self.clone().try_map(|c| c.eval(ctx))
+        let mut entity = EntityMap::new();
+
+        for name in self.removed_components() {
+            entity.remove_component(name);
+        }
+
+        for (name, component) in self.components() {
+            entity.add_component(name, component.eval(ctx)?);
+        }
+
+        for child in self.children() {
+            entity.add_child(Value::Entity(child.eval_to_entity(ctx)?));
+        }
+
+        Ok(entity)
+    }
+
+    /// Components of `overrides` merged on top of these ones, with the ones
+    /// `overrides` removes dropped. Children listed by `overrides`, even none,
+    /// replace these ones
+    pub(crate) fn merged(&self, overrides: &EntityExpr) -> EntityExpr {
+        let mut entity = self.clone();
+
+        for name in overrides.removed_components() {
+            entity.remove_component(name);
+        }
+
+        for (name, component) in overrides.components() {
+            let component = match self.get_component(name) {
+                Some(base) => base.merged(component),
+                None => component.clone(),
+            };
+            entity.add_component(name, component);
+        }
+
+        if overrides.lists_children() {
+            entity.set_children(overrides.children().cloned().collect());
+        }
+
+        entity
+    }
+
+    /// Asset paths referenced by components and children
+    pub(crate) fn collect_refs<'e>(&'e self, refs: &mut AssetRefs<'e>) {
+        for (_, component) in self.components() {
+            component.collect_refs(refs);
+        }
+
+        for child in self.children() {
+            child.collect_refs(refs);
+        }
     }
 }
 
@@ -75,13 +319,119 @@ impl<T> EntityExprBuilder<T> {
 //#[derive(PartialEq, Debug, Deserialize, Serialize)]
 //#[serde(untagged)]
 #[derive(PartialEq, Debug, Clone, Deserialize)]
-#[serde(from = "parse::ParsedExprNode")]
+#[serde(try_from = "parse::ParsedExprNode")]
 pub enum Expr {
-    KeyMap(HashMap<String, Expr>),
+    KeyMap(IndexMap<String, Expr>),
     Constant(Value),
     Vec(Vec<Expr>),
     Entity(EntityMap<Expr>),
     Func(String, Vec<Expr>),
+    /// Random choice between `(weight, expr)` entries, with `pick` set this
+    /// picks that many entries without replacement into a list
+    Weighted {
+        entries: Vec<(f32, Expr)>,
+        pick: Option<usize>,
+    },
+    /// Parameter supplied when the blueprint is inserted
+    Param(String),
+    /// Variables visible while evaluating the body
+    Let(IndexMap<String, Expr>, Box<Expr>),
+    /// Variable bound by an enclosing [`Expr::Let`]
+    Var(String),
+    /// Entity from the blueprint at an asset path
+    Blueprint(String),
+    /// [`Value::Option`] holding the value of the expression
+    Some(Box<Expr>),
+    /// [`Value::Named`] with the value of the expression as its content
+    Named(String, Box<Expr>),
+}
+
+/// Expression evaluating to `value`, shaped like the expression parsed from
+/// it so it is written out and loaded back the same
+impl From<Value> for Expr {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::KeyMap(map) => Expr::KeyMap(
+                map.into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+            Value::Vec(values) => Expr::Vec(values.into_iter().map(Expr::from).collect()),
+            Value::Option(Some(value)) => Expr::Some(Box::new((*value).into())),
+            Value::Named(name, content) => Expr::Named(name, Box::new((*content).into())),
+            Value::Entity(entity) => Expr::Entity(entity.map(Expr::from)),
+            value => Expr::Constant(value),
+        }
+    }
+}
+
+impl Expr {
+    /// `overrides` on top of this expression, maps are merged key by key and
+    /// anything else is replaced
+    pub(crate) fn merged(&self, overrides: &Expr) -> Expr {
+        match (self, overrides) {
+            (Expr::KeyMap(base), Expr::KeyMap(overrides)) => {
+                let mut merged = base.clone();
+
+                for (key, value) in overrides {
+                    let value = match base.get(key) {
+                        Some(base_value) => base_value.merged(value),
+                        None => value.clone(),
+                    };
+                    merged.insert(key.clone(), value);
+                }
+
+                Expr::KeyMap(merged)
+            }
+            // Named structs keep their name when the fields are overridden
+            (Expr::Named(name, base), Expr::Named(overrides_name, overrides))
+                if name == overrides_name =>
+            {
+                Expr::Named(name.clone(), Box::new(base.merged(overrides)))
+            }
+            (Expr::Named(name, base), overrides @ Expr::KeyMap(_)) => {
+                Expr::Named(name.clone(), Box::new(base.merged(overrides)))
+            }
+            (base @ Expr::KeyMap(_), Expr::Named(name, overrides)) => {
+                Expr::Named(name.clone(), Box::new(base.merged(overrides)))
+            }
+            (_, overrides) => overrides.clone(),
+        }
+    }
+
+    /// Asset paths this expression references
+    pub(crate) fn collect_refs<'e>(&'e self, refs: &mut AssetRefs<'e>) {
+        match self {
+            Expr::KeyMap(values) => {
+                for value in values.values() {
+                    value.collect_refs(refs);
+                }
+            }
+            Expr::Let(bindings, body) => {
+                for value in bindings.values() {
+                    value.collect_refs(refs);
+                }
+                body.collect_refs(refs);
+            }
+            Expr::Vec(values) | Expr::Func(_, values) => {
+                for value in values {
+                    value.collect_refs(refs);
+                }
+            }
+            Expr::Weighted { entries, .. } => {
+                for (_, value) in entries {
+                    value.collect_refs(refs);
+                }
+            }
+            Expr::Entity(entity) => entity.collect_refs(refs),
+            Expr::Some(value) | Expr::Named(_, value) => value.collect_refs(refs),
+            Expr::Blueprint(path) | Expr::Constant(Value::Blueprint(path)) => {
+                refs.blueprints.push(path)
+            }
+            Expr::Constant(Value::Asset(path)) => refs.assets.push(path),
+            Expr::Constant(_) | Expr::Param(_) | Expr::Var(_) => {}
+        }
+    }
 }
 
 impl Evaluatable for Expr {
@@ -107,6 +457,31 @@ impl Evaluatable for Expr {
                     .collect::<Result<_, Error>>()?;
                 ctx.call_function(func_name, &evaled_args)
             }
+            Expr::Weighted { entries, pick } => {
+                let weights: Vec<f32> = entries.iter().map(|(weight, _)| *weight).collect();
+                let picked = ctx.pick_weighted(&weights, pick.unwrap_or(1))?;
+
+                let mut values = picked
+                    .into_iter()
+                    .map(|index| entries[index].1.eval(ctx))
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                match pick {
+                    Some(_) => Ok(Value::Vec(values)),
+                    None => Ok(values.remove(0)),
+                }
+            }
+            Expr::Param(name) => ctx.param(name),
+            Expr::Let(bindings, body) => {
+                let scope = Scope::new(bindings);
+                body.eval(&ctx.scoped(&scope))
+            }
+            Expr::Var(name) => ctx.var(name),
+            Expr::Blueprint(path) => ctx.eval_blueprint(path),
+            Expr::Some(value) => Ok(Value::Option(Some(Box::new(value.eval(ctx)?)))),
+            Expr::Named(name, content) => {
+                Ok(Value::Named(name.clone(), Box::new(content.eval(ctx)?)))
+            }
         }
     }
 }
@@ -114,8 +489,11 @@ impl Evaluatable for Expr {
 #[cfg(test)]
 mod tests {
     use crate::{
-        expr::{EntityExpr, Expr},
+        expr::{Context, EntityExpr, EntityExprBuilder, Evaluatable, Expr},
+        ron_reader,
+        runtime::SimpleRuntime,
         value::Value,
+        Error,
     };
     use serde::{Deserialize, Serialize};
 
@@ -151,4 +529,315 @@ mod tests {
 
         assert_eq!(components, vec![("test_comp", &expected_test_comp_expr)]);
     }
+
+    fn test_runtime() -> SimpleRuntime {
+        let mut runtime = SimpleRuntime::new();
+
+        runtime.register_func("add", |args| {
+            Ok(Value::F32(
+                args.iter()
+                    .map(|arg| match arg {
+                        Value::F32(v) => *v,
+                        Value::I32(v) => *v as f32,
+                        _ => 0.0,
+                    })
+                    .sum(),
+            ))
+        });
+        runtime.register_func("half", |args| match args {
+            [Value::F32(v)] => Ok(Value::F32(v / 2.0)),
+            [unexpected] => Err(Error::UnexpectedType {
+                unexpected: unexpected.typename(),
+                expected: "f32",
+            }),
+            _ => Err(Error::UnexpectedType {
+                unexpected: "vec",
+                expected: "f32",
+            }),
+        });
+
+        runtime
+    }
+
+    #[test]
+    fn test_eval_func() {
+        let runtime = test_runtime();
+        let expr = Expr::Func(
+            "add".to_string(),
+            vec![
+                Expr::Constant(Value::F32(40.0)),
+                Expr::Constant(Value::I32(2)),
+            ],
+        );
+
+        assert_eq!(
+            expr.eval(&Context::new(&runtime)).unwrap(),
+            Value::F32(42.0)
+        );
+    }
+
+    #[test]
+    fn test_eval_nested_func() {
+        let runtime = test_runtime();
+        let expr = Expr::Func(
+            "half".to_string(),
+            vec![Expr::Func(
+                "add".to_string(),
+                vec![
+                    Expr::Constant(Value::F32(80.0)),
+                    Expr::Func("add".to_string(), vec![Expr::Constant(Value::I32(4))]),
+                ],
+            )],
+        );
+
+        assert_eq!(
+            expr.eval(&Context::new(&runtime)).unwrap(),
+            Value::F32(42.0)
+        );
+    }
+
+    #[test]
+    fn test_eval_func_in_key_map_and_vec() {
+        let runtime = test_runtime();
+        let entity_expr = EntityExprBuilder::new()
+            .add_component(
+                "test_comp",
+                Expr::KeyMap(
+                    vec![
+                        (
+                            "x".to_string(),
+                            Expr::Func(
+                                "add".to_string(),
+                                vec![
+                                    Expr::Constant(Value::F32(41.0)),
+                                    Expr::Constant(Value::F32(1.0)),
+                                ],
+                            ),
+                        ),
+                        ("y".to_string(), Expr::Constant(Value::F32(-5.0))),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+            )
+            .add_component(
+                "test_vec",
+                Expr::Vec(vec![
+                    Expr::Constant(Value::I32(1)),
+                    Expr::Func("half".to_string(), vec![Expr::Constant(Value::F32(4.0))]),
+                ]),
+            )
+            .build();
+
+        let entity = entity_expr.eval(&Context::new(&runtime)).unwrap();
+        let components: std::collections::HashMap<_, _> = entity.components().collect();
+
+        assert_eq!(
+            components["test_comp"].to_component::<TestComp>().unwrap(),
+            TestComp { x: 42.0, y: -5.0 }
+        );
+        assert_eq!(
+            components["test_vec"],
+            &Value::Vec(vec![Value::I32(1), Value::F32(2.0)])
+        );
+    }
+
+    #[test]
+    fn test_eval_undefined_func() {
+        let runtime = test_runtime();
+        let expr = Expr::Vec(vec![Expr::Func("missing".to_string(), Vec::new())]);
+
+        match expr.eval(&Context::new(&runtime)) {
+            Err(Error::UndefinedFunctionError(name)) => assert_eq!(name, "missing"),
+            other => panic!("Expected undefined function error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_func_error() {
+        let runtime = test_runtime();
+        let expr = Expr::Func(
+            "half".to_string(),
+            vec![Expr::Constant(Value::String("forty two".to_string()))],
+        );
+
+        match expr.eval(&Context::new(&runtime)) {
+            Err(Error::UnexpectedType {
+                unexpected,
+                expected,
+            }) => assert_eq!((unexpected, expected), ("string", "f32")),
+            other => panic!("Expected unexpected type error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_seeded_random() {
+        let runtime = SimpleRuntime::with_stdlib();
+        let entity_expr: EntityExpr = ron_reader::from_bytes(
+            br#"
+            {
+                "Hitpoints": { "$rand_range": [80, 120] },
+                "Speed": { "$rand": [] },
+                "Attacks": [
+                    { "$choose": ["FireBreath", "Scratch", "Bark"] },
+                    { "$choose": ["FireBreath", "Scratch", "Bark"] },
+                ],
+                "Stats": (
+                    strength: { "$rand_range": [1, 1000] },
+                    agility: { "$rand_range": [1, 1000] },
+                ),
+            }
+            "#,
+        )
+        .unwrap();
+
+        let spawn = |seed| {
+            entity_expr
+                .eval(&Context::with_seed(&runtime, seed))
+                .unwrap()
+        };
+
+        assert_eq!(spawn(42), spawn(42));
+        assert_ne!(spawn(42), spawn(43));
+    }
+
+    #[test]
+    fn test_eval_weighted() {
+        let runtime = SimpleRuntime::with_stdlib();
+        let entity_expr: EntityExpr = ron_reader::from_bytes(
+            br#"
+            {
+                "Weapon": { "$weighted": [(3, "Sword"), (1.5, "Axe"), (0, "Spoon")] },
+                "Loot": { "$weighted": (
+                    pick: 2,
+                    entries: [(1, "Gold"), (1, "Gem"), (0, "Rock")],
+                ) },
+                "Minion": { "$weighted": [
+                    (1, { "$entity": { "Name": "goblin" } }),
+                    (1, { "$entity": { "Name": "orc" } }),
+                ] },
+            }
+            "#,
+        )
+        .unwrap();
+
+        for seed in 0..50 {
+            let entity = entity_expr
+                .eval(&Context::with_seed(&runtime, seed))
+                .unwrap();
+            let components: std::collections::HashMap<_, _> = entity.components().collect();
+
+            let weapon = components["Weapon"];
+            assert!(
+                weapon == &Value::String("Sword".to_string())
+                    || weapon == &Value::String("Axe".to_string())
+            );
+
+            match components["Loot"] {
+                Value::Vec(loot) => {
+                    let mut loot = loot.clone();
+                    loot.sort_by_key(|v| format!("{:?}", v));
+                    assert_eq!(
+                        loot,
+                        vec![
+                            Value::String("Gem".to_string()),
+                            Value::String("Gold".to_string())
+                        ]
+                    );
+                }
+                unexpected => panic!("Expected loot list, got {:?}", unexpected),
+            }
+
+            match components["Minion"] {
+                Value::Entity(minion) => assert_eq!(minion.components().count(), 1),
+                unexpected => panic!("Expected minion entity, got {:?}", unexpected),
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_weighted_overflow() {
+        let runtime = SimpleRuntime::with_stdlib();
+        let expr: Expr =
+            ron_reader::from_bytes(br#"{ "$weighted": [(3e38, "a"), (3e38, "b")] }"#).unwrap();
+
+        assert!(matches!(
+            expr.eval(&Context::with_seed(&runtime, 0)),
+            Err(Error::InvalidArguments(..))
+        ));
+
+        let expr = Expr::Weighted {
+            entries: vec![
+                (f32::MAX, Expr::from(Value::I32(1))),
+                (f32::MAX, Expr::from(Value::I32(2))),
+            ],
+            pick: Some(2),
+        };
+        assert!(matches!(
+            expr.eval(&Context::with_seed(&runtime, 0)),
+            Err(Error::InvalidArguments(..))
+        ));
+    }
+
+    #[test]
+    fn test_parse_weighted_errors() {
+        for invalid in &[
+            r#"{ "$weighted": [(-1, "Sword")] }"#,
+            r#"{ "$weighted": [(0, "Sword")] }"#,
+            r#"{ "$weighted": ["Sword"] }"#,
+            r#"{ "$weighted": (pick: 2, entries: [(1, "Sword")]) }"#,
+            r#"{ "$weighted": (count: 1, entries: [(1, "Sword")]) }"#,
+        ] {
+            assert!(
+                ron_reader::from_bytes::<Expr>(invalid.as_bytes()).is_err(),
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_func() {
+        let entity_ron = r#"
+            {
+                "test_comp": (
+                    x: { "$add": [40.0, { "$add": 2 }] },
+                    y: 42.0
+                )
+            }
+        "#;
+
+        let parsed_entity: EntityExpr = ron_reader::from_bytes(entity_ron.as_bytes()).unwrap();
+
+        let expected_test_comp_expr = Expr::KeyMap(
+            vec![
+                (
+                    "x".to_string(),
+                    Expr::Func(
+                        "add".to_string(),
+                        vec![
+                            Expr::Constant(Value::F32(40.0)),
+                            Expr::Func("add".to_string(), vec![Expr::Constant(Value::I32(2))]),
+                        ],
+                    ),
+                ),
+                ("y".to_string(), Expr::Constant(Value::F32(42.0))),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let components: Vec<(_, _)> = parsed_entity.components().collect();
+
+        assert_eq!(components, vec![("test_comp", &expected_test_comp_expr)]);
+
+        let runtime = test_runtime();
+        let entity = parsed_entity.eval(&Context::new(&runtime)).unwrap();
+        let (_, test_comp) = entity.components().next().unwrap();
+
+        assert_eq!(
+            test_comp.to_component::<TestComp>().unwrap(),
+            TestComp { x: 42.0, y: 42.0 }
+        );
+    }
 }
@@ -1,15 +1,27 @@
-use std::collections::HashMap;
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
 
-use serde::{Deserialize, Serialize};
+use indexmap::IndexMap;
+use serde::{
+    de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
-use crate::value::{EntityMap, Value};
+use crate::{
+    value::{self, widen_f32, EntityMap, Value},
+    Error,
+};
 
 use super::Expr;
 
+#[allow(dead_code)]
 #[derive(PartialEq, Debug, Serialize)]
 struct ParsedStruct {
     name: String,
-    members: HashMap<String, ParsedExprNode>,
+    members: IndexMap<String, ParsedExprNode>,
 }
 
 // struct ParsedStructVisitor;
@@ -30,32 +42,471 @@ struct ParsedStruct {
 //     }
 // }
 
-#[derive(PartialEq, Debug, Deserialize, Serialize)]
+/// Prefix marking a single entry map as a function call, e.g. `{"$add": [1, 2]}`
+const FUNC_PREFIX: char = '$';
+
+/// Function call written as a map with a single `$`-prefixed key
+#[derive(PartialEq, Debug)]
+pub struct ParsedFunc {
+    name: String,
+    args: Vec<ParsedExprNode>,
+}
+
+impl Serialize for ParsedFunc {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&format!("{}{}", FUNC_PREFIX, self.name), &self.args)?;
+        map.end()
+    }
+}
+
+/// Blueprint value as written, before `$` functions are told apart from
+/// the built-in directives
+#[derive(PartialEq, Debug, Serialize)]
 #[serde(untagged)]
 pub enum ParsedExprNode {
-    KeyMap(HashMap<String, ParsedExprNode>),
+    Func(ParsedFunc),
+    KeyMap(IndexMap<String, ParsedExprNode>),
     String(String),
+    Bool(bool),
     I32(i32),
+    I64(i64),
+    U64(u64),
+    #[serde(serialize_with = "serialize_f32")]
     F32(f32),
+    F64(f64),
+    Option(Option<Box<ParsedExprNode>>),
+    Unit,
     Vec(Vec<ParsedExprNode>),
     Entity(EntityMap<ParsedExprNode>),
-    Func(String, Vec<ParsedExprNode>),
+    /// Named struct or enum variant, holding [`ParsedExprNode::Unit`],
+    /// [`ParsedExprNode::Vec`] or [`ParsedExprNode::KeyMap`]
+    #[serde(serialize_with = "serialize_named")]
+    Named(String, Box<ParsedExprNode>),
+}
+
+/// Like [`Value::F32`], written in its shortest form even by formats that
+/// widen `f32`s with `as f64`
+fn serialize_f32<S>(v: &f32, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_f64(widen_f32(*v))
+}
+
+fn serialize_named<S>(
+    name: &str,
+    content: &ParsedExprNode,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match content {
+        ParsedExprNode::Unit => value::serialize_named::<_, ParsedExprNode>(serializer, name, None),
+        content => value::serialize_named(serializer, name, Some(content)),
+    }
+}
+
+struct ParsedExprNodeVisitor;
+
+impl<'de> Visitor<'de> for ParsedExprNodeVisitor {
+    type Value = ParsedExprNode;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a blueprint value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::Bool(v))
+    }
+
+    /// Integers are the smallest of `i32`, `i64` and `u64` they fit
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(match i32::try_from(v) {
+            Ok(v) => ParsedExprNode::I32(v),
+            Err(_) => ParsedExprNode::I64(v),
+        })
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        match i64::try_from(v) {
+            Ok(v) => self.visit_i64(v),
+            Err(_) => Ok(ParsedExprNode::U64(v)),
+        }
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::F32(v))
+    }
+
+    /// Floats are `f32` when the `f32` is written the same, like `0.1`, and
+    /// `f64` when that would lose precision
+    #[allow(clippy::float_cmp)]
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        if widen_f32(v as f32) == v || v.is_nan() {
+            Ok(ParsedExprNode::F32(v as f32))
+        } else {
+            Ok(ParsedExprNode::F64(v))
+        }
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::String(v.to_string()))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::String(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::Unit)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(ParsedExprNode::Option(None))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(ParsedExprNode::Option(Some(Box::new(
+            ParsedExprNode::deserialize(deserializer)?,
+        ))))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(ParsedExprNode::Vec(values))
+    }
+
+    /// A map with a single `$name` key is a function call, the value being
+    /// the list of arguments or the only argument
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries: Vec<(String, ParsedExprNode)> = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+
+        if let [(key, _)] = entries.as_slice() {
+            if matches!(key.strip_prefix(FUNC_PREFIX), Some(name) if !name.is_empty()) {
+                let (key, args) = entries.pop().unwrap();
+                let args = match args {
+                    ParsedExprNode::Vec(args) => args,
+                    arg => vec![arg],
+                };
+
+                return Ok(ParsedExprNode::Func(ParsedFunc {
+                    name: key[FUNC_PREFIX.len_utf8()..].to_string(),
+                    args,
+                }));
+            }
+        }
+
+        Ok(ParsedExprNode::KeyMap(entries.into_iter().collect()))
+    }
+
+    /// Named values, only formats that keep names like RON have them
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (name, content): (String, _) = data.variant()?;
+        let content: ParsedExprNode = content.newtype_variant()?;
+
+        Ok(ParsedExprNode::Named(name, Box::new(content)))
+    }
+}
+
+impl<'de> Deserialize<'de> for ParsedExprNode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ParsedExprNodeVisitor)
+    }
+}
+
+fn invalid_args(name: &str, reason: &str) -> Error {
+    Error::InvalidArguments(name.to_string(), reason.to_string())
 }
 
-impl From<ParsedExprNode> for Expr {
-    fn from(parsed_node: ParsedExprNode) -> Self {
-        match parsed_node {
-            ParsedExprNode::KeyMap(m) => {
-                Expr::KeyMap(m.into_iter().map(|(k, v)| (k, v.into())).collect())
+fn parse_all(nodes: Vec<ParsedExprNode>) -> Result<Vec<Expr>, Error> {
+    nodes.into_iter().map(Expr::try_from).collect()
+}
+
+fn parse_entity(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    match <[ParsedExprNode; 1]>::try_from(args) {
+        Ok([ParsedExprNode::KeyMap(components)]) => {
+            Ok(Expr::Entity(parse_components(name, components)?))
+        }
+        _ => Err(invalid_args(name, "expected a map of components")),
+    }
+}
+
+/// Parses a map of components where `$children` lists the child entities
+fn parse_components(
+    name: &str,
+    components: IndexMap<String, ParsedExprNode>,
+) -> Result<EntityMap<Expr>, Error> {
+    let mut entity = EntityMap::new();
+
+    for (key, node) in components {
+        match key.strip_prefix(FUNC_PREFIX) {
+            Some("children") => match node {
+                ParsedExprNode::Vec(children) => {
+                    for child in parse_children(children)? {
+                        entity.add_child(child);
+                    }
+                }
+                _ => return Err(invalid_args(name, "expected a list of children")),
+            },
+            Some(_) => {
+                return Err(invalid_args(
+                    name,
+                    &format!("unknown entity setting `{}`", key),
+                ))
+            }
+            None => entity.add_component(&key, node.try_into()?),
+        }
+    }
+
+    Ok(entity)
+}
+
+/// Parses child entities, each either a map of components or the asset path
+/// of a blueprint
+fn parse_children(nodes: Vec<ParsedExprNode>) -> Result<Vec<Expr>, Error> {
+    nodes
+        .into_iter()
+        .map(|node| match node {
+            ParsedExprNode::String(path) => Ok(Expr::Blueprint(path)),
+            ParsedExprNode::KeyMap(components) => {
+                Ok(Expr::Entity(parse_components("children", components)?))
+            }
+            // A child with nothing but children of its own parses as a call
+            ParsedExprNode::Func(ParsedFunc { name, args }) if name == "children" => {
+                let mut entity = EntityMap::new();
+                for child in parse_children(args)? {
+                    entity.add_child(child);
+                }
+                Ok(Expr::Entity(entity))
+            }
+            ParsedExprNode::Func(ParsedFunc { name, args }) if name == "entity" => {
+                parse_entity(&name, args)
+            }
+            ParsedExprNode::Func(ParsedFunc { name, args }) if name == "blueprint" => {
+                Ok(Expr::Blueprint(parse_path(&name, args)?))
             }
+            _ => Err(invalid_args(
+                "children",
+                "expected a map of components or a blueprint path",
+            )),
+        })
+        .collect()
+}
+
+/// Child entities listed under `$children` in a blueprint
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "Vec<ParsedExprNode>")]
+pub(crate) struct ParsedChildren(pub Vec<Expr>);
+
+impl TryFrom<Vec<ParsedExprNode>> for ParsedChildren {
+    type Error = Error;
+
+    fn try_from(nodes: Vec<ParsedExprNode>) -> Result<Self, Error> {
+        Ok(ParsedChildren(parse_children(nodes)?))
+    }
+}
+
+fn parse_path(name: &str, args: Vec<ParsedExprNode>) -> Result<String, Error> {
+    match <[ParsedExprNode; 1]>::try_from(args) {
+        Ok([ParsedExprNode::String(path)]) => Ok(path),
+        _ => Err(invalid_args(name, "expected an asset path")),
+    }
+}
+
+fn parse_param(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    match <[ParsedExprNode; 1]>::try_from(args) {
+        Ok([ParsedExprNode::String(param)]) => Ok(Expr::Param(param)),
+        _ => Err(invalid_args(name, "expected a parameter name")),
+    }
+}
+
+fn parse_var(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    match <[ParsedExprNode; 1]>::try_from(args) {
+        Ok([ParsedExprNode::String(var)]) => Ok(Expr::Var(var)),
+        _ => Err(invalid_args(name, "expected a variable name")),
+    }
+}
+
+/// Parses `[name]`, `[name, [a, b]]` or `[name, { field: a }]`, how named
+/// structs and variants are written in formats without names like JSON
+fn parse_named(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    let mut args = args.into_iter();
+
+    match (args.next(), args.next(), args.next()) {
+        (Some(ParsedExprNode::String(named)), content, None) => {
+            let content = match content {
+                None => ParsedExprNode::Unit,
+                Some(
+                    content @ (ParsedExprNode::Unit
+                    | ParsedExprNode::Vec(_)
+                    | ParsedExprNode::KeyMap(_)),
+                ) => content,
+                Some(_) => return Err(invalid_args(name, "expected a list or map of fields")),
+            };
+
+            Ok(Expr::Named(named, Box::new(content.try_into()?)))
+        }
+        _ => Err(invalid_args(name, "expected a name and optional fields")),
+    }
+}
+
+/// Parses `[{ name: value, ... }, body]`
+fn parse_let(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    match <[ParsedExprNode; 2]>::try_from(args) {
+        Ok([ParsedExprNode::KeyMap(bindings), body]) => Ok(Expr::Let(
+            bindings
+                .into_iter()
+                .map(|(k, v)| Ok((k, v.try_into()?)))
+                .collect::<Result<_, Error>>()?,
+            Box::new(body.try_into()?),
+        )),
+        _ => Err(invalid_args(name, "expected a map of variables and a body")),
+    }
+}
+
+/// Parses the `(pick: n, entries: [...])` form of a weighted choice
+fn parse_weighted_options(
+    name: &str,
+    mut options: IndexMap<String, ParsedExprNode>,
+) -> Result<(Vec<ParsedExprNode>, Option<usize>), Error> {
+    let entries = match options.remove("entries") {
+        Some(ParsedExprNode::Vec(entries)) => entries,
+        _ => return Err(invalid_args(name, "expected a list of `entries`")),
+    };
+
+    let pick = match options.remove("pick") {
+        Some(ParsedExprNode::I32(count)) => Some(
+            usize::try_from(count)
+                .map_err(|_| invalid_args(name, "`pick` must not be negative"))?,
+        ),
+        Some(_) => return Err(invalid_args(name, "`pick` must be an integer")),
+        None => None,
+    };
+
+    if let Some(unknown) = options.keys().next() {
+        return Err(invalid_args(name, &format!("unknown option `{}`", unknown)));
+    }
+
+    Ok((entries, pick))
+}
+
+/// Parses `[(weight, value), ...]` entries or `(pick: n, entries: [...])`
+fn parse_weighted(name: &str, args: Vec<ParsedExprNode>) -> Result<Expr, Error> {
+    let (entries, pick) = match <[ParsedExprNode; 1]>::try_from(args) {
+        Ok([ParsedExprNode::KeyMap(options)]) => parse_weighted_options(name, options)?,
+        Ok([entry]) => (vec![entry], None),
+        Err(args) => (args, None),
+    };
+
+    let entries = entries
+        .into_iter()
+        .map(|entry| {
+            let pair = match entry {
+                ParsedExprNode::Vec(pair) => pair,
+                _ => return Err(invalid_args(name, "expected `(weight, value)` entries")),
+            };
+
+            let (weight, value) = match <[ParsedExprNode; 2]>::try_from(pair) {
+                Ok([ParsedExprNode::I32(weight), value]) => (weight as f32, value),
+                Ok([ParsedExprNode::F32(weight), value]) => (weight, value),
+                Ok([ParsedExprNode::F64(weight), value]) => (weight as f32, value),
+                _ => return Err(invalid_args(name, "expected `(weight, value)` entries")),
+            };
+
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(invalid_args(
+                    name,
+                    "weights must be finite and not negative",
+                ));
+            }
+
+            Ok((weight, Expr::try_from(value)?))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    if entries.iter().all(|(weight, _)| *weight == 0.0) {
+        return Err(invalid_args(
+            name,
+            "expected at least one entry with a positive weight",
+        ));
+    }
+
+    if matches!(pick, Some(count) if count > entries.len()) {
+        return Err(invalid_args(
+            name,
+            "`pick` is larger than the number of entries",
+        ));
+    }
+
+    Ok(Expr::Weighted { entries, pick })
+}
+
+impl TryFrom<ParsedExprNode> for Expr {
+    type Error = Error;
+
+    fn try_from(parsed_node: ParsedExprNode) -> Result<Self, Error> {
+        Ok(match parsed_node {
+            ParsedExprNode::KeyMap(m) => Expr::KeyMap(
+                m.into_iter()
+                    .map(|(k, v)| Ok((k, v.try_into()?)))
+                    .collect::<Result<_, Error>>()?,
+            ),
             ParsedExprNode::String(v) => Expr::Constant(Value::String(v)),
+            ParsedExprNode::Bool(v) => Expr::Constant(Value::Bool(v)),
             ParsedExprNode::I32(v) => Expr::Constant(Value::I32(v)),
+            ParsedExprNode::I64(v) => Expr::Constant(Value::I64(v)),
+            ParsedExprNode::U64(v) => Expr::Constant(Value::U64(v)),
             ParsedExprNode::F32(v) => Expr::Constant(Value::F32(v)),
-            ParsedExprNode::Vec(nodes) => Expr::Vec(nodes.into_iter().map(|n| n.into()).collect()),
-            ParsedExprNode::Entity(entity_map) => Expr::Entity(entity_map.map(|c| c.into())),
-            ParsedExprNode::Func(name, args) => {
-                Expr::Func(name, args.into_iter().map(|n| n.into()).collect())
+            ParsedExprNode::F64(v) => Expr::Constant(Value::F64(v)),
+            ParsedExprNode::Unit => Expr::Constant(Value::Unit),
+            ParsedExprNode::Option(None) => Expr::Constant(Value::Option(None)),
+            ParsedExprNode::Option(Some(node)) => Expr::Some(Box::new((*node).try_into()?)),
+            ParsedExprNode::Named(name, content) => {
+                Expr::Named(name, Box::new((*content).try_into()?))
             }
-        }
+            ParsedExprNode::Vec(nodes) => Expr::Vec(parse_all(nodes)?),
+            ParsedExprNode::Entity(entity_map) => Expr::Entity(entity_map.try_map(Expr::try_from)?),
+            ParsedExprNode::Func(ParsedFunc { name, args }) => match name.as_str() {
+                "entity" => parse_entity(&name, args)?,
+                "blueprint" => Expr::Constant(Value::Blueprint(parse_path(&name, args)?)),
+                "asset" => Expr::Constant(Value::Asset(parse_path(&name, args)?)),
+                "weighted" => parse_weighted(&name, args)?,
+                "param" => parse_param(&name, args)?,
+                "var" => parse_var(&name, args)?,
+                "let" => parse_let(&name, args)?,
+                "named" => parse_named(&name, args)?,
+                _ => Expr::Func(name, parse_all(args)?),
+            },
+        })
     }
 }
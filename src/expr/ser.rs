@@ -0,0 +1,54 @@
+//! Writing expressions back out in the syntax they are parsed from, so a
+//! saved blueprint loads back into the same expressions
+
+use serde::{Serialize, Serializer};
+
+use super::Expr;
+use crate::value::{serialize_func, serialize_named, Value};
+
+/// `(pick: n, entries: [...])` form of a weighted choice
+#[derive(Serialize)]
+struct WeightedOptions<'a> {
+    pick: usize,
+    entries: &'a [(f32, Expr)],
+}
+
+impl Serialize for Expr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Expr::KeyMap(map) => serializer.collect_map(map),
+            Expr::Constant(value) => value.serialize(serializer),
+            Expr::Vec(values) => serializer.collect_seq(values),
+            Expr::Entity(entity) => serialize_func(serializer, "entity", entity),
+            Expr::Func(name, args) => serialize_func(serializer, name, args),
+            Expr::Weighted {
+                entries,
+                pick: None,
+            } => serialize_func(serializer, "weighted", entries),
+            Expr::Weighted {
+                entries,
+                pick: Some(pick),
+            } => serialize_func(
+                serializer,
+                "weighted",
+                &WeightedOptions {
+                    pick: *pick,
+                    entries,
+                },
+            ),
+            Expr::Param(name) => serialize_func(serializer, "param", name),
+            Expr::Let(bindings, body) => serialize_func(serializer, "let", &(bindings, body)),
+            Expr::Var(name) => serialize_func(serializer, "var", name),
+            // Only found among `$children`, where a blueprint is its path
+            Expr::Blueprint(path) => serializer.serialize_str(path),
+            Expr::Some(value) => serializer.serialize_some(value),
+            Expr::Named(name, content) => match **content {
+                Expr::Constant(Value::Unit) => serialize_named::<_, Expr>(serializer, name, None),
+                _ => serialize_named(serializer, name, Some(content)),
+            },
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Weighted random choice
+
+use rand::{Rng, RngCore};
+
+/// Picks `count` distinct indices into `weights`, each draw proportional to the
+/// weights of the entries that have not been picked yet. Fails if there are
+/// fewer than `count` entries with a positive weight, or if the weights add
+/// up to more than an `f32` can hold.
+pub(crate) fn pick_weighted(
+    rng: &mut dyn RngCore,
+    weights: &[f32],
+    count: usize,
+) -> Result<Vec<usize>, String> {
+    let mut remaining: Vec<(usize, f32)> = weights
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect();
+
+    if count > remaining.len() {
+        return Err(format!(
+            "cannot pick {} entries with a positive weight",
+            count
+        ));
+    }
+
+    // Totals only shrink as entries are picked, so checking the first one is
+    // enough
+    let total: f32 = remaining.iter().map(|(_, weight)| weight).sum();
+    if !total.is_finite() {
+        return Err("the total weight is not finite".to_string());
+    }
+
+    let mut picked = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let total: f32 = remaining.iter().map(|(_, weight)| weight).sum();
+        let mut target = rng.gen_range(0.0..total);
+
+        // Rounding can leave `target` just past the last entry
+        let position = remaining
+            .iter()
+            .position(|(_, weight)| {
+                if target < *weight {
+                    true
+                } else {
+                    target -= weight;
+                    false
+                }
+            })
+            .unwrap_or(remaining.len() - 1);
+
+        picked.push(remaining.remove(position).0);
+    }
+
+    Ok(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::pick_weighted;
+
+    #[test]
+    fn test_pick_weighted_distribution() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut counts = [0; 3];
+
+        for _ in 0..10_000 {
+            let picked = pick_weighted(&mut rng, &[1.0, 0.0, 3.0], 1).unwrap();
+            counts[picked[0]] += 1;
+        }
+
+        assert_eq!(counts[1], 0);
+        assert!((2000..3000).contains(&counts[0]), "{:?}", counts);
+        assert!((7000..8000).contains(&counts[2]), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_pick_weighted_without_replacement() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let mut picked = pick_weighted(&mut rng, &[1.0, 100.0, 0.5, 0.0], 3).unwrap();
+            picked.sort_unstable();
+            assert_eq!(picked, vec![0, 1, 2]);
+        }
+
+        assert!(pick_weighted(&mut rng, &[1.0, 0.0], 2).is_err());
+        assert!(pick_weighted(&mut rng, &[3e38, 3e38], 1).is_err());
+        assert!(pick_weighted(&mut rng, &[f32::INFINITY], 1).is_err());
+    }
+}
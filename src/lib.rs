@@ -1,22 +1,33 @@
 //! Prints a blueprint system for entity components systems
 
-use bevy::reflect::TypeUuid;
+use bevy::{asset::Handle, reflect::TypeUuid};
 use expr::Evaluatable;
 
 use std::ffi::OsStr;
-use tracing::info;
+use tracing::{debug, info};
 
 pub mod bevy_prints;
 pub mod expr;
+mod ron_reader;
+mod ron_writer;
 mod runtime;
 pub mod value;
 
 use crate::{
-    expr::{EntityExpr, Expr},
-    value::EntityMap,
+    expr::{AssetRefs, EntityExpr, Expr, ParsedChildren, Scope},
+    value::{EntityMap, Value},
+};
+use indexmap::IndexMap;
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
-    fs::File,
+    borrow::Cow,
+    collections::HashMap,
+    convert::TryFrom,
+    fmt, fs,
     path::{Path, PathBuf},
 };
 use thiserror::Error;
@@ -29,32 +40,356 @@ pub enum Error {
         unexpected: &'static str,
         expected: &'static str,
     },
+    #[error("`{name}` given for a `{expected}`")]
+    UnexpectedName { name: String, expected: String },
     #[error("Could not determine entity name from path {0:?}")]
     CouldNotDetermineEntityName(PathBuf),
     #[error("Error loading {0:?}: {1}")]
     LoadError(PathBuf, #[source] std::io::Error),
     #[error("Error parsing {0:?}: {1}")]
     ParseError(String, anyhow::Error),
-    #[error("Error creating component")]
+    #[error("Error writing {0:?}: {1}")]
+    WriteError(String, anyhow::Error),
+    #[error("Error saving {0:?}: {1}")]
+    SaveError(PathBuf, #[source] std::io::Error),
+    #[error("Error creating component: {0}")]
     ToComponentError(#[from] value::ToComponentError),
     #[error("Function `{0}` not defined")]
     UndefinedFunctionError(String),
+    #[error("Invalid arguments for `{0}`: {1}")]
+    InvalidArguments(String, String),
     #[error("Unknown component `{0}`")]
     UnknownComponent(String),
+    #[error("{value} is out of range for {target}")]
+    NumberOutOfRange { value: String, target: &'static str },
+    #[error("`{type_name}` has no field `{field}`")]
+    UnknownField {
+        type_name: &'static str,
+        field: String,
+    },
+    #[error("`{0}` is not registered with `#[reflect(Component)]`")]
+    NotAComponent(String),
+    #[error("`{0}` is not registered for reflection")]
+    UnregisteredType(String),
+    #[error("The AssetServer resource is needed for `$asset` values")]
+    MissingAssetServer,
+    #[error("The TypeRegistry resource is needed for reflected components")]
+    MissingTypeRegistry,
+    #[error("The {0} resource is needed for blueprints, it is added by the PrintsPlugin")]
+    MissingResource(&'static str),
+    #[error("Entity {0:?} does not exist")]
+    NoSuchEntity(bevy::ecs::entity::Entity),
+    #[error("`{0}` has no asset path")]
+    UnknownAssetPath(String),
+    #[error("`{0}` is reflected as a value that can't be serialized")]
+    NotSerializable(String),
+    #[error("Missing required parameter `{0}`")]
+    MissingParameter(String),
+    #[error("Parameter `{0}` not declared")]
+    UndefinedParameter(String),
+    #[error("Variable `{0}` not defined")]
+    UndefinedVariable(String),
+    #[error("Variable `{0}` depends on itself")]
+    CyclicVariable(String),
+    #[error("Blueprint `{0}` is not loaded")]
+    BlueprintNotLoaded(String),
+    #[error("Blueprint `{0}` depends on itself")]
+    CyclicBlueprint(String),
+    #[error("Component `{component}` is defined by both mixin `{first}` and `{second}`")]
+    MixinConflict {
+        component: String,
+        first: String,
+        second: String,
+    },
+    #[error("Parameter `{name}` has type {unexpected}, expected {expected}")]
+    ParameterType {
+        name: String,
+        unexpected: &'static str,
+        expected: &'static str,
+    },
+}
+
+/// Type of a blueprint parameter, named like [`Value::typename`]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ParamType {
+    String,
+    Bool,
+    I32,
+    F32,
+    Vec,
+    Map,
+    Entity,
+}
+
+impl TryFrom<String> for ParamType {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_str() {
+            "string" => Ok(ParamType::String),
+            "bool" => Ok(ParamType::Bool),
+            "i32" => Ok(ParamType::I32),
+            "f32" => Ok(ParamType::F32),
+            "vec" => Ok(ParamType::Vec),
+            "map" => Ok(ParamType::Map),
+            "entity" => Ok(ParamType::Entity),
+            _ => Err(format!("Unknown parameter type `{}`", name)),
+        }
+    }
+}
+
+impl Serialize for ParamType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.typename())
+    }
+}
+
+impl ParamType {
+    pub fn typename(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Bool => "bool",
+            ParamType::I32 => "i32",
+            ParamType::F32 => "f32",
+            ParamType::Vec => "vec",
+            ParamType::Map => "map",
+            ParamType::Entity => "entity",
+        }
+    }
+
+    /// Checks `value` has this type, any number is accepted for `f32`
+    /// parameters and integers in range for `i32` ones
+    fn check(self, name: &str, value: Value) -> Result<Value, Error> {
+        match (self, value) {
+            (ty, value) if value.typename() == ty.typename() => Ok(value),
+            (ParamType::F32, value) if value.is_number() => {
+                Ok(Value::F32(value.to_number().ok_or_else(|| {
+                    Error::NumberOutOfRange {
+                        value: value.number_string(),
+                        target: "f32",
+                    }
+                })?))
+            }
+            (ParamType::I32, value @ (Value::I64(_) | Value::U64(_))) => {
+                Ok(Value::I32(value.to_number().ok_or_else(|| {
+                    Error::NumberOutOfRange {
+                        value: value.number_string(),
+                        target: "i32",
+                    }
+                })?))
+            }
+            (ty, value) => Err(Error::ParameterType {
+                name: name.to_string(),
+                unexpected: value.typename(),
+                expected: ty.typename(),
+            }),
+        }
+    }
+}
+
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Writes the value of a field skipped when `None`, the counterpart of
+/// [`deserialize_some`]
+fn serialize_some<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Parameter declared by a blueprint, without a default it must be supplied
+/// when the blueprint is inserted
+#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
+pub struct ParamDecl {
+    #[serde(
+        rename = "type",
+        default,
+        deserialize_with = "deserialize_some",
+        serialize_with = "serialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    ty: Option<ParamType>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        serialize_with = "serialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    default: Option<Expr>,
+}
+
+/// Blueprint file contents, a map of components along with `$`-prefixed
+/// blueprint settings
+struct BlueprintFile {
+    extends: Option<String>,
+    mixins: Vec<String>,
+    strict_mixins: bool,
+    params: IndexMap<String, ParamDecl>,
+    bindings: IndexMap<String, Expr>,
+    removed: Vec<String>,
+    children: Option<Vec<Expr>>,
+    components: EntityMap<Expr>,
+}
+
+struct BlueprintFileVisitor;
+
+impl<'de> Visitor<'de> for BlueprintFileVisitor {
+    type Value = BlueprintFile;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map of components")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut file = BlueprintFile {
+            extends: None,
+            mixins: Vec::new(),
+            strict_mixins: false,
+            params: IndexMap::new(),
+            bindings: IndexMap::new(),
+            removed: Vec::new(),
+            children: None,
+            components: EntityMap::new(),
+        };
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "$extends" => file.extends = Some(map.next_value()?),
+                "$mixins" => file.mixins = map.next_value()?,
+                "$strict_mixins" => file.strict_mixins = map.next_value()?,
+                "$params" => file.params = map.next_value()?,
+                "$let" => file.bindings = map.next_value()?,
+                "$remove" => file.removed = map.next_value()?,
+                "$children" => file.children = Some(map.next_value::<ParsedChildren>()?.0),
+                setting if setting.starts_with('$') => {
+                    return Err(de::Error::custom(format!(
+                        "Unknown blueprint setting `{}`",
+                        setting
+                    )))
+                }
+                _ => file.components.add_component(&key, map.next_value()?),
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlueprintFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(BlueprintFileVisitor)
+    }
+}
+
+/// Written like it is read by [`BlueprintFileVisitor`], settings first then
+/// the components in order
+impl Serialize for Blueprint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+
+        if let Some(extends) = &self.extends {
+            map.serialize_entry("$extends", extends)?;
+        }
+        if !self.mixins.is_empty() {
+            map.serialize_entry("$mixins", &self.mixins)?;
+        }
+        if self.strict_mixins {
+            map.serialize_entry("$strict_mixins", &true)?;
+        }
+        if !self.params.is_empty() {
+            map.serialize_entry("$params", &self.params)?;
+        }
+        if !self.bindings.is_empty() {
+            map.serialize_entry("$let", &self.bindings)?;
+        }
+
+        let removed: Vec<&str> = self.entity.removed_components().collect();
+        if !removed.is_empty() {
+            map.serialize_entry("$remove", &removed)?;
+        }
+        // An empty `$children` still replaces the children of the blueprint
+        // this one extends
+        if self.entity.lists_children() {
+            let children: Vec<&Expr> = self.entity.children().collect();
+            map.serialize_entry("$children", &children)?;
+        }
+
+        for (name, component) in self.entity.components() {
+            map.serialize_entry(name, component)?;
+        }
+
+        map.end()
+    }
+}
+
+/// `overrides` on top of `base`
+fn merged_map<T: Clone>(
+    base: &IndexMap<String, T>,
+    overrides: &IndexMap<String, T>,
+) -> IndexMap<String, T> {
+    let mut merged = base.clone();
+    merged.extend(
+        overrides
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    merged
 }
 
 /// Blueprint for creating an entity.
-#[derive(Debug, TypeUuid)]
+#[derive(Debug, Clone, TypeUuid)]
 #[uuid = "1e71b7ab-6867-4711-8ac7-51538edf2403"]
 pub struct Blueprint {
     #[allow(dead_code)]
     name: String,
-    expr: Expr,
+    /// Asset path of the blueprint this one is based on
+    extends: Option<String>,
+    /// Asset paths of blueprints merged in after `extends`, in order
+    mixins: Vec<String>,
+    /// Two mixins defining the same component is an error rather than the
+    /// later one winning
+    strict_mixins: bool,
+    params: IndexMap<String, ParamDecl>,
+    bindings: IndexMap<String, Expr>,
+    entity: EntityExpr,
+    /// Strong handles to [`Blueprint::dependencies`], set by the asset loader
+    /// to keep them loaded
+    dependency_handles: Vec<Handle<Blueprint>>,
 }
 
 impl Evaluatable for Blueprint {
     fn eval(&self, ctx: &expr::Context) -> Result<value::Value, Error> {
-        self.expr.eval(ctx)
+        let blueprint = self.flattened(ctx, &mut Vec::new())?;
+
+        let params = blueprint.resolve_params(ctx)?;
+        let ctx = ctx.scoped_params(params);
+        let scope = Scope::new(&blueprint.bindings);
+
+        Ok(Value::Entity(blueprint.entity.eval(&ctx.scoped(&scope))?))
     }
 }
 
@@ -62,62 +397,350 @@ impl Blueprint {
     pub fn new(name: impl Into<String>, entity: EntityExpr) -> Self {
         Blueprint {
             name: name.into(),
-            expr: Expr::Entity(entity),
+            extends: None,
+            mixins: Vec::new(),
+            strict_mixins: false,
+            params: IndexMap::new(),
+            bindings: IndexMap::new(),
+            entity,
+            dependency_handles: Vec::new(),
         }
     }
 
-    /// Load blueprint from a json file
-    pub fn load_from_json(filename: &Path) -> Result<Blueprint, Error> {
-        let blueprint_file =
-            File::open(filename).map_err(|e| Error::LoadError(filename.to_owned(), e))?;
+    /// Asset paths of the blueprints this one needs to be evaluated
+    pub fn dependencies(&self) -> impl Iterator<Item = &str> {
+        let mut blueprints = self.refs().blueprints;
+        blueprints.extend(
+            self.extends
+                .iter()
+                .chain(self.mixins.iter())
+                .map(String::as_str),
+        );
+
+        blueprints.sort_unstable();
+        blueprints.dedup();
+        blueprints.into_iter()
+    }
+
+    /// Asset paths of the other assets referenced with `$asset`
+    pub fn asset_dependencies(&self) -> impl Iterator<Item = &str> {
+        let mut assets = self.refs().assets;
+
+        assets.sort_unstable();
+        assets.dedup();
+        assets.into_iter()
+    }
+
+    fn refs(&self) -> AssetRefs<'_> {
+        let mut refs = AssetRefs::default();
+
+        for expr in self.bindings.values() {
+            expr.collect_refs(&mut refs);
+        }
+        for default in self
+            .params
+            .values()
+            .filter_map(|decl| decl.default.as_ref())
+        {
+            default.collect_refs(&mut refs);
+        }
+        self.entity.collect_refs(&mut refs);
+
+        refs
+    }
+
+    /// This blueprint merged on top of the blueprint it extends and then its
+    /// mixins in order, components, parameters and variables of later ones
+    /// win while map components are merged key by key
+    fn flattened(
+        &self,
+        ctx: &expr::Context,
+        visiting: &mut Vec<String>,
+    ) -> Result<Cow<'_, Blueprint>, Error> {
+        if self.extends.is_none() && self.mixins.is_empty() {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        let mut blueprint = match &self.extends {
+            Some(path) => Blueprint::flattened_dependency(ctx, path, visiting)?,
+            None => Blueprint::new(self.name.clone(), EntityMap::new()),
+        };
+
+        // Component name to the mixin defining it, for strict mode
+        let mut mixed_in: HashMap<String, &String> = HashMap::new();
+
+        for path in &self.mixins {
+            let mixin = Blueprint::flattened_dependency(ctx, path, visiting)?;
+
+            if self.strict_mixins {
+                for (name, _) in mixin.entity.components() {
+                    if let Some(first) = mixed_in.insert(name.to_string(), path) {
+                        return Err(Error::MixinConflict {
+                            component: name.to_string(),
+                            first: first.clone(),
+                            second: path.clone(),
+                        });
+                    }
+                }
+            }
+
+            blueprint = blueprint.merged(&mixin);
+        }
+
+        Ok(Cow::Owned(blueprint.merged(self)))
+    }
+
+    /// Blueprint at `path` flattened, `visiting` holds the paths being
+    /// flattened further up to catch blueprints depending on themselves
+    fn flattened_dependency(
+        ctx: &expr::Context,
+        path: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Blueprint, Error> {
+        if visiting.iter().any(|visited| visited == path) {
+            return Err(Error::CyclicBlueprint(path.to_string()));
+        }
 
+        visiting.push(path.to_string());
+        let blueprint = ctx.blueprint(path)?.flattened(ctx, visiting)?.into_owned();
+        visiting.pop();
+
+        Ok(blueprint)
+    }
+
+    /// `overrides` on top of this blueprint, ignoring what either extends
+    fn merged(&self, overrides: &Blueprint) -> Blueprint {
+        Blueprint {
+            name: overrides.name.clone(),
+            extends: None,
+            mixins: Vec::new(),
+            strict_mixins: false,
+            params: merged_map(&self.params, &overrides.params),
+            bindings: merged_map(&self.bindings, &overrides.bindings),
+            entity: self.entity.merged(&overrides.entity),
+            dependency_handles: Vec::new(),
+        }
+    }
+
+    fn from_file(filename: &Path, file: BlueprintFile) -> Result<Blueprint, Error> {
         let name = filename
             .file_stem()
             .and_then(OsStr::to_str)
             .ok_or_else(|| Error::CouldNotDetermineEntityName(filename.to_owned()))?;
 
-        let expr: Expr = serde_json::from_reader(blueprint_file).map_err(|e| {
+        info!(filename=?filename, "Loaded blueprint");
+
+        debug!(blueprint_data=?file.components, "blueprint data");
+
+        let mut entity = file.components;
+        for component in &file.removed {
+            entity.remove_component(component);
+        }
+        if let Some(children) = file.children {
+            entity.set_children(children);
+        }
+
+        Ok(Blueprint {
+            name: name.to_string(),
+            extends: file.extends,
+            mixins: file.mixins,
+            strict_mixins: file.strict_mixins,
+            params: file.params,
+            bindings: file.bindings,
+            entity,
+            dependency_handles: Vec::new(),
+        })
+    }
+
+    /// Supplied parameter values with defaults filled in for the ones left out
+    fn resolve_params(&self, ctx: &expr::Context) -> Result<HashMap<String, Value>, Error> {
+        if let Some(unknown) = ctx
+            .params()
+            .keys()
+            .find(|name| !self.params.contains_key(*name))
+        {
+            return Err(Error::UndefinedParameter(unknown.clone()));
+        }
+
+        // Sorted so defaults drawing random values are reproducible
+        let mut names: Vec<&String> = self.params.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let decl = &self.params[name];
+
+                let value = match (ctx.params().get(name), &decl.default) {
+                    (Some(value), _) => value.clone(),
+                    (None, Some(default)) => default.eval(ctx)?,
+                    (None, None) => return Err(Error::MissingParameter(name.clone())),
+                };
+
+                let value = match decl.ty {
+                    Some(ty) => ty.check(name, value)?,
+                    None => value,
+                };
+
+                Ok((name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Load blueprint from a json file
+    pub fn load_from_json(filename: &Path) -> Result<Blueprint, Error> {
+        let data = fs::read(filename).map_err(|e| Error::LoadError(filename.to_owned(), e))?;
+
+        Blueprint::load_from_json_bytes(filename, &data)
+    }
+
+    pub fn load_from_json_bytes(filename: &Path, data: &[u8]) -> Result<Blueprint, Error> {
+        let file: BlueprintFile = serde_json::from_slice(data).map_err(|e| {
             Error::ParseError(
                 filename.to_string_lossy().to_string(),
                 anyhow::Error::new(e),
             )
         })?;
 
-        Ok(Blueprint {
-            name: name.to_string(),
-            expr,
-        })
+        Blueprint::from_file(filename, file)
     }
 
     pub fn load_from_ron_bytes(filename: &Path, data: &[u8]) -> Result<Blueprint, Error> {
-        let name = filename
-            .file_stem()
-            .and_then(OsStr::to_str)
-            .ok_or_else(|| Error::CouldNotDetermineEntityName(filename.to_owned()))?;
+        let file: BlueprintFile = ron_reader::from_bytes(data).map_err(|e| {
+            Error::ParseError(
+                filename.to_string_lossy().to_string(),
+                anyhow::Error::new(e),
+            )
+        })?;
+
+        Blueprint::from_file(filename, file)
+    }
 
-        let expr: EntityMap<Expr> = ron::de::from_bytes(data).map_err(|e| {
+    #[cfg(feature = "yaml")]
+    pub fn load_from_yaml_bytes(filename: &Path, data: &[u8]) -> Result<Blueprint, Error> {
+        let file: BlueprintFile = serde_yaml::from_slice(data).map_err(|e| {
             Error::ParseError(
                 filename.to_string_lossy().to_string(),
                 anyhow::Error::new(e),
             )
         })?;
 
-        info!(filename=?filename, "Loaded blueprint");
+        Blueprint::from_file(filename, file)
+    }
 
-        info!(blueprint_data=?expr, "blueprint data");
+    #[cfg(feature = "toml")]
+    pub fn load_from_toml_bytes(filename: &Path, data: &[u8]) -> Result<Blueprint, Error> {
+        let file: BlueprintFile = std::str::from_utf8(data)
+            .map_err(anyhow::Error::new)
+            .and_then(|source| toml::from_str(source).map_err(anyhow::Error::new))
+            .map_err(|e| Error::ParseError(filename.to_string_lossy().to_string(), e))?;
 
-        Ok(Blueprint {
-            name: name.to_string(),
-            expr: Expr::Entity(expr),
-        })
+        Blueprint::from_file(filename, file)
+    }
+
+    /// Load blueprint in the format named by the extension of `filename`,
+    /// JSON for `.json`, YAML for `.yaml` and TOML for `.toml` when those
+    /// features are enabled, and RON otherwise
+    pub fn load_from_bytes(filename: &Path, data: &[u8]) -> Result<Blueprint, Error> {
+        match filename.extension().and_then(OsStr::to_str) {
+            Some("json") => Blueprint::load_from_json_bytes(filename, data),
+            #[cfg(feature = "yaml")]
+            Some("yaml") => Blueprint::load_from_yaml_bytes(filename, data),
+            #[cfg(feature = "toml")]
+            Some("toml") => Blueprint::load_from_toml_bytes(filename, data),
+            _ => Blueprint::load_from_ron_bytes(filename, data),
+        }
+    }
+
+    fn write_error(&self, e: impl Into<anyhow::Error>) -> Error {
+        Error::WriteError(self.name.clone(), e.into())
+    }
+
+    /// Blueprint as pretty-printed RON, keeping the names of named structs
+    /// and variants
+    pub fn to_ron_string(&self) -> Result<String, Error> {
+        ron_writer::to_string_pretty(self).map_err(|e| self.write_error(e))
+    }
+
+    /// Blueprint as pretty-printed JSON, named structs and variants written
+    /// as `$named` calls
+    pub fn to_json_string(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| self.write_error(e))
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml_string(&self) -> Result<String, Error> {
+        serde_yaml::to_string(self).map_err(|e| self.write_error(e))
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn to_toml_string(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self).map_err(|e| self.write_error(e))
+    }
+
+    /// Blueprint in the format named by the extension of `filename`, like
+    /// [`Blueprint::load_from_bytes`] reads it
+    pub fn to_bytes(&self, filename: &Path) -> Result<Vec<u8>, Error> {
+        let written = match filename.extension().and_then(OsStr::to_str) {
+            Some("json") => self.to_json_string(),
+            #[cfg(feature = "yaml")]
+            Some("yaml") => self.to_yaml_string(),
+            #[cfg(feature = "toml")]
+            Some("toml") => self.to_toml_string(),
+            _ => self.to_ron_string(),
+        }?;
+
+        Ok(written.into_bytes())
+    }
+
+    /// Save blueprint to `filename` in the format named by its extension
+    pub fn save(&self, filename: &Path) -> Result<(), Error> {
+        let data = self.to_bytes(filename)?;
+
+        fs::write(filename, data).map_err(|e| Error::SaveError(filename.to_owned(), e))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::{collections::HashMap, path::Path};
+
+    use crate::{
+        expr::{BlueprintLibrary, Context, Evaluatable, Expr},
+        runtime::SimpleRuntime,
+        value::{EntityMap, Value},
+        Blueprint, Error,
+    };
 
-    use crate::Blueprint;
+    impl BlueprintLibrary for HashMap<String, Blueprint> {
+        fn get_blueprint(&self, path: &str) -> Option<&Blueprint> {
+            self.get(path)
+        }
+    }
+
+    fn library(blueprints: &[(&str, &str)]) -> HashMap<String, Blueprint> {
+        blueprints
+            .iter()
+            .map(|(path, source)| {
+                let blueprint =
+                    Blueprint::load_from_ron_bytes(Path::new(path), source.as_bytes()).unwrap();
+                (path.to_string(), blueprint)
+            })
+            .collect()
+    }
+
+    fn eval_from_library(
+        blueprints: &HashMap<String, Blueprint>,
+        path: &str,
+    ) -> Result<HashMap<String, Value>, Error> {
+        let runtime = SimpleRuntime::with_stdlib();
+        let ctx = Context::new(&runtime).with_blueprints(blueprints);
+
+        Ok(blueprints[path]
+            .eval_to_entity(&ctx)?
+            .into_components()
+            .collect())
+    }
 
     #[test]
     fn test_test_blueprint_parses() {
@@ -136,4 +759,1033 @@ mod tests {
         )
         .expect("assets/blueprints/example.bp.ron parses");
     }
+
+    #[test]
+    fn test_example_blueprint_formats_match() {
+        let ron = Blueprint::load_from_bytes(
+            Path::new("../assets/blueprints/example.bp.ron"),
+            include_bytes!("../assets/blueprints/example.bp.ron"),
+        )
+        .unwrap();
+        let others: &[(&str, &[u8])] = &[
+            (
+                "../assets/blueprints/example.bp.json",
+                include_bytes!("../assets/blueprints/example.bp.json"),
+            ),
+            #[cfg(feature = "yaml")]
+            (
+                "../assets/blueprints/example.bp.yaml",
+                include_bytes!("../assets/blueprints/example.bp.yaml"),
+            ),
+            #[cfg(feature = "toml")]
+            (
+                "../assets/blueprints/example.bp.toml",
+                include_bytes!("../assets/blueprints/example.bp.toml"),
+            ),
+        ];
+
+        for (filename, source) in others {
+            let other = Blueprint::load_from_bytes(Path::new(filename), source)
+                .unwrap_or_else(|e| panic!("{} does not parse: {}", filename, e));
+
+            assert_eq!(ron.name, other.name, "{}", filename);
+            assert_eq!(ron.params, other.params, "{}", filename);
+            assert_eq!(ron.entity, other.entity, "{}", filename);
+            assert_eq!(
+                eval_with_params(&ron, Vec::new()).unwrap(),
+                eval_with_params(&other, Vec::new()).unwrap(),
+                "{}",
+                filename
+            );
+
+            let named = |name: &str| ("name", Value::String(name.to_string()));
+            assert_eq!(
+                eval_with_params(&ron, vec![named("shiba")]).unwrap(),
+                eval_with_params(&other, vec![named("shiba")]).unwrap(),
+                "{}",
+                filename
+            );
+        }
+    }
+
+    fn eval_with_params(
+        blueprint: &Blueprint,
+        params: Vec<(&str, Value)>,
+    ) -> Result<HashMap<String, Value>, Error> {
+        let runtime = SimpleRuntime::with_stdlib();
+        let ctx = Context::new(&runtime).with_params(
+            params
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        );
+
+        Ok(blueprint.eval_to_entity(&ctx)?.into_components().collect())
+    }
+
+    #[test]
+    fn test_blueprint_params() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"
+            {
+                "$params": {
+                    "name": (type: "string"),
+                    "hitpoints": (type: "f32", default: { "$mul": [2, 50] }),
+                    "loot": (),
+                },
+                "Name": { "$param": "name" },
+                "Hitpoints": { "$param": "hitpoints" },
+                "Loot": { "$param": "loot" },
+            }
+            "#,
+        )
+        .unwrap();
+
+        let name = || ("name", Value::String("goblin".to_string()));
+        let loot = || ("loot", Value::Vec(Vec::new()));
+
+        let components = eval_with_params(&blueprint, vec![name(), loot()]).unwrap();
+        assert_eq!(components["Name"], Value::String("goblin".to_string()));
+        assert_eq!(components["Hitpoints"], Value::F32(100.0));
+        assert_eq!(components["Loot"], Value::Vec(Vec::new()));
+
+        let components = eval_with_params(
+            &blueprint,
+            vec![name(), loot(), ("hitpoints", Value::I32(80))],
+        )
+        .unwrap();
+        assert_eq!(components["Hitpoints"], Value::F32(80.0));
+
+        match eval_with_params(&blueprint, vec![loot()]) {
+            Err(Error::MissingParameter(param)) => assert_eq!(param, "name"),
+            other => panic!("Expected missing parameter error, got {:?}", other),
+        }
+
+        match eval_with_params(&blueprint, vec![loot(), ("name", Value::I32(1))]) {
+            Err(Error::ParameterType { name, .. }) => assert_eq!(name, "name"),
+            other => panic!("Expected parameter type error, got {:?}", other),
+        }
+
+        match eval_with_params(&blueprint, vec![name(), loot(), ("speed", Value::I32(1))]) {
+            Err(Error::UndefinedParameter(param)) => assert_eq!(param, "speed"),
+            other => panic!("Expected undefined parameter error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undeclared_param_reference() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"{ "Name": { "$param": "name" } }"#,
+        )
+        .unwrap();
+
+        match eval_with_params(&blueprint, Vec::new()) {
+            Err(Error::UndefinedParameter(param)) => assert_eq!(param, "name"),
+            other => panic!("Expected undefined parameter error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_blueprint_let() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"
+            {
+                "$params": { "level": (type: "i32", default: 2) },
+                "$let": {
+                    "run_speed": { "$mul": [{ "$var": "base_speed" }, 2] },
+                    "base_speed": { "$add": [4, { "$param": "level" }] },
+                },
+                "Speed": (
+                    walk: { "$var": "base_speed" },
+                    run: { "$var": "run_speed" },
+                ),
+                "Speeds": [{ "$var": "base_speed" }, { "$var": "run_speed" }],
+                "Local": { "$let": [
+                    { "base_speed": 1 },
+                    [{ "$var": "base_speed" }, { "$var": "run_speed" }],
+                ] },
+            }
+            "#,
+        )
+        .unwrap();
+
+        let components = eval_with_params(&blueprint, Vec::new()).unwrap();
+
+        assert_eq!(
+            components["Speed"],
+            Value::KeyMap(
+                vec![
+                    ("walk".to_string(), Value::I32(6)),
+                    ("run".to_string(), Value::I32(12)),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(
+            components["Speeds"],
+            Value::Vec(vec![Value::I32(6), Value::I32(12)])
+        );
+        assert_eq!(
+            components["Local"],
+            Value::Vec(vec![Value::I32(1), Value::I32(12)])
+        );
+    }
+
+    #[test]
+    fn test_blueprint_let_errors() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"{ "Speed": { "$var": "speed" } }"#,
+        )
+        .unwrap();
+
+        match eval_with_params(&blueprint, Vec::new()) {
+            Err(Error::UndefinedVariable(var)) => assert_eq!(var, "speed"),
+            other => panic!("Expected undefined variable error, got {:?}", other),
+        }
+
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"
+            {
+                "$let": {
+                    "a": { "$add": [{ "$var": "b" }, 1] },
+                    "b": { "$add": [{ "$var": "a" }, 1] },
+                },
+                "Speed": { "$var": "a" },
+            }
+            "#,
+        )
+        .unwrap();
+
+        match eval_with_params(&blueprint, Vec::new()) {
+            Err(Error::CyclicVariable(var)) => assert_eq!(var, "a"),
+            other => panic!("Expected cyclic variable error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_blueprint_extends() {
+        let blueprints = library(&[
+            (
+                "enemy.bp.ron",
+                r#"
+                {
+                    "$params": { "level": (default: 1) },
+                    "$let": { "speed": 5.0 },
+                    "Name": "enemy",
+                    "Hitpoints": { "$mul": [{ "$param": "level" }, 10] },
+                    "Movement": (speed: { "$var": "speed" }, flying: 0, gait: (step: 1, sway: 2)),
+                    "Collider": (radius: 1.0),
+                }
+                "#,
+            ),
+            (
+                "goblin.bp.ron",
+                r#"
+                {
+                    "$extends": "enemy.bp.ron",
+                    "$params": { "level": (default: 3) },
+                    "Name": "goblin",
+                    "Movement": (flying: 1, gait: (sway: 4)),
+                }
+                "#,
+            ),
+            (
+                "fast_goblin.bp.ron",
+                r#"
+                {
+                    "$extends": "goblin.bp.ron",
+                    "$let": { "speed": 9.0 },
+                    "Collider": "none",
+                }
+                "#,
+            ),
+        ]);
+
+        let components = eval_from_library(&blueprints, "fast_goblin.bp.ron").unwrap();
+
+        let map = |entries: Vec<(&str, Value)>| {
+            Value::KeyMap(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+        };
+
+        assert_eq!(components["Name"], Value::String("goblin".to_string()));
+        assert_eq!(components["Hitpoints"], Value::I32(30));
+        assert_eq!(
+            components["Movement"],
+            map(vec![
+                ("speed", Value::F32(9.0)),
+                ("flying", Value::I32(1)),
+                (
+                    "gait",
+                    map(vec![("step", Value::I32(1)), ("sway", Value::I32(4))])
+                ),
+            ])
+        );
+        assert_eq!(components["Collider"], Value::String("none".to_string()));
+    }
+
+    #[test]
+    fn test_blueprint_extends_errors() {
+        let blueprints = library(&[
+            ("orphan.bp.ron", r#"{ "$extends": "missing.bp.ron" }"#),
+            ("a.bp.ron", r#"{ "$extends": "b.bp.ron" }"#),
+            ("b.bp.ron", r#"{ "$extends": "a.bp.ron" }"#),
+            ("c.bp.ron", r#"{ "$mixins": ["c.bp.ron"] }"#),
+        ]);
+
+        match eval_from_library(&blueprints, "orphan.bp.ron") {
+            Err(Error::BlueprintNotLoaded(path)) => assert_eq!(path, "missing.bp.ron"),
+            other => panic!("Expected blueprint not loaded error, got {:?}", other),
+        }
+
+        assert!(matches!(
+            eval_from_library(&blueprints, "a.bp.ron"),
+            Err(Error::CyclicBlueprint(_))
+        ));
+        assert!(matches!(
+            eval_from_library(&blueprints, "c.bp.ron"),
+            Err(Error::CyclicBlueprint(_))
+        ));
+    }
+
+    #[test]
+    fn test_blueprint_mixins() {
+        let blueprints = library(&[
+            (
+                "physics.bp.ron",
+                r#"{ "Collider": (radius: 1.0), "Mass": 10.0 }"#,
+            ),
+            (
+                "flammable.bp.ron",
+                r#"
+                {
+                    "$extends": "physics.bp.ron",
+                    "$params": { "burn_time": (default: 3.0) },
+                    "Flammable": (burn_time: { "$param": "burn_time" }),
+                    "Mass": 5.0,
+                }
+                "#,
+            ),
+            (
+                "pickup.bp.ron",
+                r#"{ "$extends": "physics.bp.ron", "Collider": (trigger: 1) }"#,
+            ),
+            (
+                "crate.bp.ron",
+                r#"
+                {
+                    "$mixins": ["flammable.bp.ron", "pickup.bp.ron"],
+                    "Name": "crate",
+                    "Mass": 20.0,
+                }
+                "#,
+            ),
+            (
+                "strict_crate.bp.ron",
+                r#"
+                {
+                    "$mixins": ["flammable.bp.ron", "pickup.bp.ron"],
+                    "$strict_mixins": true,
+                }
+                "#,
+            ),
+        ]);
+
+        let components = eval_from_library(&blueprints, "crate.bp.ron").unwrap();
+
+        assert_eq!(components["Name"], Value::String("crate".to_string()));
+        assert_eq!(components["Mass"], Value::F32(20.0));
+        assert_eq!(
+            components["Collider"],
+            Value::KeyMap(
+                vec![
+                    ("radius".to_string(), Value::F32(1.0)),
+                    ("trigger".to_string(), Value::I32(1)),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(
+            components["Flammable"],
+            Value::KeyMap(
+                vec![("burn_time".to_string(), Value::F32(3.0))]
+                    .into_iter()
+                    .collect()
+            )
+        );
+
+        match eval_from_library(&blueprints, "strict_crate.bp.ron") {
+            Err(Error::MixinConflict { first, second, .. }) => {
+                assert_eq!(first, "flammable.bp.ron");
+                assert_eq!(second, "pickup.bp.ron");
+            }
+            other => panic!("Expected mixin conflict error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_blueprint_remove() {
+        let blueprints = library(&[
+            (
+                "enemy.bp.ron",
+                r#"{ "Name": "enemy", "Collider": (radius: 1.0), "Loot": [] }"#,
+            ),
+            (
+                "ghost.bp.ron",
+                r#"
+                {
+                    "$extends": "enemy.bp.ron",
+                    "$remove": ["Collider", "Shadow"],
+                    "Name": "ghost",
+                }
+                "#,
+            ),
+            (
+                "solid_ghost.bp.ron",
+                r#"{ "$extends": "ghost.bp.ron", "Collider": (radius: 2.0) }"#,
+            ),
+        ]);
+
+        let runtime = SimpleRuntime::new();
+        let ctx = Context::new(&runtime).with_blueprints(&blueprints);
+
+        let ghost = blueprints["ghost.bp.ron"].eval_to_entity(&ctx).unwrap();
+        assert_eq!(ghost.get_component("Collider"), None);
+        assert!(ghost.get_component("Loot").is_some());
+        assert_eq!(
+            ghost.removed_components().collect::<Vec<_>>(),
+            vec!["Collider", "Shadow"]
+        );
+
+        let solid_ghost = blueprints["solid_ghost.bp.ron"]
+            .eval_to_entity(&ctx)
+            .unwrap();
+        assert!(solid_ghost.get_component("Collider").is_some());
+        assert_eq!(
+            solid_ghost.removed_components().collect::<Vec<_>>(),
+            vec!["Shadow"]
+        );
+    }
+
+    #[test]
+    fn test_blueprint_children() {
+        let blueprints = library(&[
+            (
+                "bow.bp.ron",
+                r#"{ "$params": { "range": (default: 10) }, "Range": { "$param": "range" } }"#,
+            ),
+            (
+                "archer.bp.ron",
+                r#"
+                {
+                    "$params": { "range": (default: 3) },
+                    "Name": "archer",
+                    "$children": [
+                        "bow.bp.ron",
+                        {
+                            "Name": "hitbox",
+                            "Range": { "$param": "range" },
+                            "$children": [{ "Name": "head" }],
+                        },
+                    ],
+                }
+                "#,
+            ),
+            ("loop.bp.ron", r#"{ "$children": ["loop.bp.ron"] }"#),
+        ]);
+
+        assert_eq!(
+            blueprints["archer.bp.ron"]
+                .dependencies()
+                .collect::<Vec<_>>(),
+            vec!["bow.bp.ron"]
+        );
+
+        let runtime = SimpleRuntime::new();
+        let ctx = Context::new(&runtime).with_blueprints(&blueprints);
+
+        let mut archer = blueprints["archer.bp.ron"].eval_to_entity(&ctx).unwrap();
+        let children = archer.take_children();
+
+        assert_eq!(
+            archer.get_component("Name"),
+            Some(&Value::String("archer".to_string()))
+        );
+
+        match children.as_slice() {
+            [Value::Entity(bow), Value::Entity(hitbox)] => {
+                assert_eq!(bow.get_component("Range"), Some(&Value::I32(10)));
+                assert_eq!(hitbox.get_component("Range"), Some(&Value::I32(3)));
+                assert_eq!(
+                    hitbox.children().collect::<Vec<_>>(),
+                    vec![&Value::Entity(
+                        vec![("Name".to_string(), Value::String("head".to_string()))]
+                            .into_iter()
+                            .collect()
+                    )]
+                );
+            }
+            other => panic!("Expected two child entities, got {:?}", other),
+        }
+
+        assert!(matches!(
+            eval_from_library(&blueprints, "loop.bp.ron"),
+            Err(Error::CyclicBlueprint(_))
+        ));
+
+        assert!(Blueprint::load_from_ron_bytes(
+            Path::new("archer.bp.ron"),
+            br#"{ "$children": [1] }"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_extends_children() {
+        let blueprints = library(&[
+            (
+                "archer.bp.ron",
+                r#"{ "$children": [{ "Name": "bow" }, { "Name": "quiver" }] }"#,
+            ),
+            (
+                "crossbowman.bp.ron",
+                r#"{ "$extends": "archer.bp.ron", "$children": [{ "Name": "crossbow" }] }"#,
+            ),
+            (
+                "unarmed.bp.ron",
+                r#"{ "$extends": "archer.bp.ron", "$children": [] }"#,
+            ),
+            (
+                "captain.bp.ron",
+                r#"{ "$extends": "archer.bp.ron", "Name": "captain" }"#,
+            ),
+        ]);
+
+        let runtime = SimpleRuntime::new();
+        let ctx = Context::new(&runtime).with_blueprints(&blueprints);
+
+        let child_names = |path: &str| -> Vec<Value> {
+            blueprints[path]
+                .eval_to_entity(&ctx)
+                .unwrap()
+                .take_children()
+                .into_iter()
+                .map(|child| match child {
+                    Value::Entity(child) => child.get_component("Name").unwrap().clone(),
+                    other => panic!("Expected a child entity, got {:?}", other),
+                })
+                .collect()
+        };
+        let names = |names: &[&str]| -> Vec<Value> {
+            names
+                .iter()
+                .map(|name| Value::String(name.to_string()))
+                .collect()
+        };
+
+        assert_eq!(child_names("crossbowman.bp.ron"), names(&["crossbow"]));
+        assert_eq!(child_names("unarmed.bp.ron"), names(&[]));
+        assert_eq!(child_names("captain.bp.ron"), names(&["bow", "quiver"]));
+    }
+
+    #[test]
+    fn test_blueprint_reference() {
+        let blueprints = library(&[
+            ("arrow.bp.ron", r#"{ "Name": "arrow" }"#),
+            (
+                "bow.bp.ron",
+                r#"
+                {
+                    "Spawner": (spawns: { "$blueprint": "arrow.bp.ron" }),
+                    "Model": { "$asset": "models/bow.glb#Scene0" },
+                    "$children": [{ "$blueprint": "arrow.bp.ron" }],
+                }
+                "#,
+            ),
+        ]);
+
+        assert_eq!(
+            blueprints["bow.bp.ron"].dependencies().collect::<Vec<_>>(),
+            vec!["arrow.bp.ron"]
+        );
+        assert_eq!(
+            blueprints["bow.bp.ron"]
+                .asset_dependencies()
+                .collect::<Vec<_>>(),
+            vec!["models/bow.glb#Scene0"]
+        );
+
+        let runtime = SimpleRuntime::new();
+        let ctx = Context::new(&runtime).with_blueprints(&blueprints);
+
+        let mut bow = blueprints["bow.bp.ron"].eval_to_entity(&ctx).unwrap();
+
+        assert_eq!(
+            bow.get_component("Spawner"),
+            Some(&Value::KeyMap(
+                vec![(
+                    "spawns".to_string(),
+                    Value::Blueprint("arrow.bp.ron".to_string())
+                )]
+                .into_iter()
+                .collect()
+            ))
+        );
+        assert_eq!(
+            bow.get_component("Model"),
+            Some(&Value::Asset("models/bow.glb#Scene0".to_string()))
+        );
+
+        match bow.take_children().as_slice() {
+            [Value::Entity(arrow)] => assert_eq!(
+                arrow.get_component("Name"),
+                Some(&Value::String("arrow".to_string()))
+            ),
+            other => panic!("Expected an arrow child, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_blueprint_setting() {
+        assert!(Blueprint::load_from_ron_bytes(
+            Path::new("goblin.bp.ron"),
+            br#"{ "$parms": {}, "Name": "goblin" }"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_blueprint_value_types() {
+        let blueprints = library(&[(
+            "values.bp.ron",
+            r#"
+            {
+                "$params": { "visible": (type: "bool", default: true) },
+                "Visible": { "$param": "visible" },
+                "Flags": (enabled: false, marker: ()),
+                "Big": (seed: 5000000000, id: 18446744073709551615),
+                "Precise": (exact: 0.5, short: 0.1, fine: 0.123456789012),
+                "Target": (none: None, some: Some(2), computed: Some({ "$add": [1, 2] })),
+            }
+            "#,
+        )]);
+
+        let components = eval_from_library(&blueprints, "values.bp.ron").unwrap();
+
+        let map = |entries: Vec<(&str, Value)>| {
+            Value::KeyMap(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect(),
+            )
+        };
+
+        assert_eq!(components["Visible"], Value::Bool(true));
+        assert_eq!(
+            components["Flags"],
+            map(vec![
+                ("enabled", Value::Bool(false)),
+                ("marker", Value::Unit)
+            ])
+        );
+        assert_eq!(
+            components["Big"],
+            map(vec![
+                ("seed", Value::I64(5_000_000_000)),
+                ("id", Value::U64(u64::MAX)),
+            ])
+        );
+        assert_eq!(
+            components["Precise"],
+            map(vec![
+                ("exact", Value::F32(0.5)),
+                ("short", Value::F32(0.1)),
+                ("fine", Value::F64(0.123456789012)),
+            ])
+        );
+        assert_eq!(
+            components["Target"],
+            map(vec![
+                ("none", Value::Option(None)),
+                ("some", Value::Option(Some(Box::new(Value::I32(2))))),
+                ("computed", Value::Option(Some(Box::new(Value::I32(3))))),
+            ])
+        );
+
+        let some_unit = Value::Option(Some(Box::new(Value::Unit)));
+        assert_eq!(some_unit.to_component::<Option<()>>().unwrap(), Some(()));
+        assert_eq!(
+            Value::F32(0.1).to_component::<f64>().unwrap(),
+            0.1,
+            "f32s are widened as written"
+        );
+    }
+
+    #[test]
+    fn test_blueprint_named_values() {
+        let blueprints = library(&[(
+            "named.bp.ron",
+            r#"
+            {
+                "Transform": Transform(translation: (1.0, 0.0, 0.0)),
+                "Attacks": [Bark, Bite(3), Breath(range: { "$add": [2, 2] }), r#Move()],
+            }
+            "#,
+        )]);
+
+        let components = eval_from_library(&blueprints, "named.bp.ron").unwrap();
+
+        let named = |name: &str, value| Value::Named(name.to_string(), Box::new(value));
+
+        assert_eq!(
+            components["Transform"],
+            named(
+                "Transform",
+                Value::KeyMap(
+                    vec![(
+                        "translation".to_string(),
+                        Value::Vec(vec![Value::F32(1.0), Value::F32(0.0), Value::F32(0.0)])
+                    )]
+                    .into_iter()
+                    .collect()
+                )
+            )
+        );
+        assert_eq!(
+            components["Attacks"],
+            Value::Vec(vec![
+                named("Bark", Value::Unit),
+                named("Bite", Value::Vec(vec![Value::I32(3)])),
+                named(
+                    "Breath",
+                    Value::KeyMap(
+                        vec![("range".to_string(), Value::I32(4))]
+                            .into_iter()
+                            .collect()
+                    )
+                ),
+                named("Move", Value::Unit),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_blueprint_component_order() {
+        let blueprints = library(&[
+            (
+                "base.bp.ron",
+                r#"
+                {
+                    "Zeta": 1,
+                    "Alpha": (z: 1, a: 2, m: 3),
+                    "Mid": 2,
+                }
+                "#,
+            ),
+            (
+                "ordered.bp.ron",
+                r#"
+                {
+                    "$extends": "base.bp.ron",
+                    "Beta": 3,
+                    "Alpha": (b: 4, z: 5),
+                    "Aardvark": 4,
+                }
+                "#,
+            ),
+        ]);
+
+        let runtime = SimpleRuntime::with_stdlib();
+        let ctx = Context::new(&runtime).with_blueprints(&blueprints);
+        let entity = blueprints["ordered.bp.ron"].eval_to_entity(&ctx).unwrap();
+
+        let names: Vec<&str> = entity.components().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["Zeta", "Alpha", "Mid", "Beta", "Aardvark"]);
+
+        match entity.get_component("Alpha") {
+            Some(Value::KeyMap(fields)) => {
+                let keys: Vec<&str> = fields.keys().map(String::as_str).collect();
+                assert_eq!(keys, vec!["z", "a", "m", "b"]);
+            }
+            other => panic!("Expected map, got {:?}", other),
+        }
+    }
+
+    const FIXTURES: &[(&str, &[u8])] = &[
+        (
+            "example.bp.ron",
+            include_bytes!("../assets/blueprints/example.bp.ron"),
+        ),
+        (
+            "test.bp.ron",
+            include_bytes!("../assets/blueprints/test.bp.ron"),
+        ),
+        (
+            "test_base.bp.ron",
+            include_bytes!("../assets/blueprints/test_base.bp.ron"),
+        ),
+        (
+            "test_extends.bp.ron",
+            include_bytes!("../assets/blueprints/test_extends.bp.ron"),
+        ),
+        (
+            "test_spawner.bp.ron",
+            include_bytes!("../assets/blueprints/test_spawner.bp.ron"),
+        ),
+    ];
+
+    fn assert_same_blueprint(expected: &Blueprint, loaded: &Blueprint, filename: &str) {
+        assert_eq!(expected.extends, loaded.extends, "{}", filename);
+        assert_eq!(expected.mixins, loaded.mixins, "{}", filename);
+        assert_eq!(expected.strict_mixins, loaded.strict_mixins, "{}", filename);
+        assert_eq!(expected.params, loaded.params, "{}", filename);
+        assert_eq!(expected.bindings, loaded.bindings, "{}", filename);
+        assert_eq!(expected.entity, loaded.entity, "{}", filename);
+    }
+
+    /// Saves `blueprint` in the format of `filename` and loads it back
+    fn save_and_reload(blueprint: &Blueprint, filename: &str) -> Blueprint {
+        let saved = blueprint.to_bytes(Path::new(filename)).unwrap();
+
+        Blueprint::load_from_bytes(Path::new(filename), &saved).unwrap_or_else(|e| {
+            panic!(
+                "{} does not load: {}\n{}",
+                filename,
+                e,
+                String::from_utf8_lossy(&saved)
+            )
+        })
+    }
+
+    #[test]
+    fn test_fixtures_save_round_trip() {
+        for (filename, source) in FIXTURES {
+            let blueprint = Blueprint::load_from_bytes(Path::new(filename), source).unwrap();
+
+            let extensions = [
+                ".ron",
+                ".json",
+                #[cfg(feature = "yaml")]
+                ".yaml",
+                #[cfg(feature = "toml")]
+                ".toml",
+            ];
+
+            for extension in extensions {
+                let saved_filename = filename.replace(".ron", extension);
+                let loaded = save_and_reload(&blueprint, &saved_filename);
+                assert_same_blueprint(&blueprint, &loaded, &saved_filename);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blueprint_save_round_trip() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("saved.bp.ron"),
+            br#"
+            {
+                "$extends": "base.bp.ron",
+                "$mixins": ["wings.bp.ron"],
+                "$strict_mixins": true,
+                "$params": {
+                    "size": (type: "f32", default: 1.5),
+                    "name": (type: "string"),
+                    "loot": (),
+                },
+                "$let": {
+                    "hp": { "$mul": [{ "$param": "size" }, 100] },
+                    "half": { "$div": [{ "$var": "hp" }, 2] },
+                },
+                "$remove": ["Sprite"],
+                "$children": [
+                    "bow.bp.ron",
+                    { "Name": "hitbox", "$children": [{ "Name": "head" }] },
+                ],
+                "Name": { "$param": "name" },
+                "Transform": Transform(translation: (1.0, 0.0, 0.0), scale: 0.1),
+                "Attacks": [Bark, Bite(3), Breath(range: { "$var": "half" }), "Claw"],
+                "Loot": Some({ "$param": "loot" }),
+                "Weapon": None,
+                "Drops": { "$weighted": (pick: 2, entries: [(1, "bone"), (2.5, "stick")]) },
+                "Colour": { "$weighted": [(1, Red), (1, Blue)] },
+                "Mood": { "$let": [{ "x": 1 }, { "$var": "x" }] },
+                "Big": 5000000000,
+                "Flags": { "alive": true, "odd key": false },
+                "Empty": (),
+                "Bytes": [],
+                "Text": "line\nwith \"quotes\"",
+                "Scene": { "$asset": "models/corgi.glb#Scene0" },
+                "Spawns": { "$blueprint": "blueprints/test_base.bp.ron" },
+                "Child": { "$entity": { "Name": "inner" } },
+            }
+            "#,
+        )
+        .unwrap();
+
+        let loaded = save_and_reload(&blueprint, "saved.bp.ron");
+        assert_same_blueprint(&blueprint, &loaded, "saved.bp.ron");
+
+        // JSON has no options, `Some(x)` is written as `x` and `None` as `null`
+        let mut blueprint = blueprint;
+        blueprint.entity.remove_component("Loot");
+        blueprint.entity.remove_component("Weapon");
+
+        let loaded = save_and_reload(&blueprint, "saved.bp.json");
+        assert_same_blueprint(&blueprint, &loaded, "saved.bp.json");
+    }
+
+    #[test]
+    fn test_blueprint_ron_output() {
+        let blueprint = Blueprint::load_from_ron_bytes(
+            Path::new("output.bp.ron"),
+            br#"{ "Zeta": Scale(y: 2, x: 1), "Alpha": [Bark, Bite(3)], "Mid": { "$param": "mid" } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            blueprint.to_ron_string().unwrap(),
+            r#"{
+    "Zeta": Scale(
+        y: 2,
+        x: 1,
+    ),
+    "Alpha": [
+        Bark,
+        Bite(3),
+    ],
+    "Mid": {
+        "$param": "mid",
+    },
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_nested_map_ron_output() {
+        let source = r#"{
+    "Stats": {
+        "health": 10,
+        "modifiers": {
+            "speed": 1.5,
+        },
+    },
+}
+"#;
+        let blueprint =
+            Blueprint::load_from_ron_bytes(Path::new("stats.bp.ron"), source.as_bytes()).unwrap();
+
+        let saved = blueprint.to_ron_string().unwrap();
+        assert_eq!(saved, source);
+
+        let reloaded =
+            Blueprint::load_from_ron_bytes(Path::new("stats.bp.ron"), saved.as_bytes()).unwrap();
+        assert_same_blueprint(&blueprint, &reloaded, "stats.bp.ron");
+    }
+
+    #[test]
+    fn test_float_save_round_trip() {
+        let floats = [
+            ("Tenth", 0.1),
+            ("Third", 1.0 / 3.0),
+            ("Tiny", 1.5e-7),
+            ("Large", 123_456.79),
+            ("Negative", -2.71),
+        ];
+
+        let mut components = EntityMap::new();
+        for (name, v) in floats {
+            components.add_component(name, Value::F32(v));
+        }
+        let blueprint = Blueprint::new("floats.bp.ron", components.map(Expr::from));
+
+        assert!(blueprint
+            .to_ron_string()
+            .unwrap()
+            .contains(r#""Tenth": 0.1,"#));
+
+        let runtime = SimpleRuntime::with_stdlib();
+        let extensions = [
+            ".ron",
+            ".json",
+            #[cfg(feature = "yaml")]
+            ".yaml",
+            #[cfg(feature = "toml")]
+            ".toml",
+        ];
+
+        for extension in extensions {
+            let filename = format!("floats.bp{}", extension);
+            let loaded = save_and_reload(&blueprint, &filename);
+            let entity = loaded
+                .entity
+                .eval(&Context::with_seed(&runtime, 0))
+                .unwrap();
+            let loaded: Vec<(&str, &Value)> = entity.components().collect();
+
+            assert_eq!(loaded.len(), floats.len(), "{}", filename);
+            for ((name, value), (expected_name, expected)) in loaded.into_iter().zip(floats) {
+                assert_eq!(name, expected_name, "{}", filename);
+                assert_eq!(value, &Value::F32(expected), "{}", filename);
+            }
+        }
+    }
+
+    /// Writes each RON fixture in another format and checks loading it back
+    /// gives the same blueprint
+    #[cfg(any(feature = "yaml", feature = "toml"))]
+    fn assert_fixtures_round_trip<F>(extension: &str, write: F)
+    where
+        F: Fn(&crate::expr::parse::ParsedExprNode) -> Vec<u8>,
+    {
+        for (filename, source) in FIXTURES {
+            let ron = Blueprint::load_from_bytes(Path::new(filename), source).unwrap();
+
+            let node = crate::ron_reader::from_bytes(source).unwrap();
+            let converted_filename = filename.replace(".ron", extension);
+            let converted = write(&node);
+            let loaded = Blueprint::load_from_bytes(Path::new(&converted_filename), &converted)
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "{} does not load: {}\n{}",
+                        converted_filename,
+                        e,
+                        String::from_utf8_lossy(&converted)
+                    )
+                });
+
+            assert_eq!(ron.name, loaded.name);
+            assert_same_blueprint(&ron, &loaded, &converted_filename);
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_round_trip() {
+        assert_fixtures_round_trip(".yaml", |node| {
+            serde_yaml::to_string(node).unwrap().into_bytes()
+        });
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_round_trip() {
+        assert_fixtures_round_trip(".toml", |node| {
+            let value = toml::Value::try_from(node).unwrap();
+            toml::to_string(&value).unwrap().into_bytes()
+        });
+    }
 }
@@ -0,0 +1,656 @@
+//! RON reader keeping the names of structs and enum variants
+//!
+//! Deserializing through `ron` with `deserialize_any` drops the name of
+//! `Transform(translation: ...)` and reads `FireBreath` as a unit. Blueprints
+//! are first read into a [`RonValue`] tree here, and deserializing from the
+//! tree hands named values to `deserialize_any` visitors as enums, the name
+//! being the variant and the content as written the newtype content.
+//!
+//! Only the nesting of values is read here: identifiers, strings, chars,
+//! numbers, comments and `#![enable(...)]` attributes are read by `ron`.
+
+use std::{convert::TryFrom, fmt};
+
+use ron::de::{Deserializer as RonDeserializer, Error, ErrorCode, Position};
+use serde::de::{
+    self,
+    value::{MapDeserializer, SeqDeserializer},
+    DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, VariantAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) enum RonValue {
+    Bool(bool),
+    Char(char),
+    String(String),
+    Int(i128),
+    Float(f64),
+    Unit,
+    Option(Option<Box<RonValue>>),
+    /// `[a, b]`
+    List(Vec<RonValue>),
+    /// `{ key: value }`
+    Map(Vec<(RonValue, RonValue)>),
+    /// `(a, b)`
+    Tuple(Vec<RonValue>),
+    /// `(field: a)`
+    Struct(Vec<(String, RonValue)>),
+    /// `Name`, `Name(a, b)` or `Name(field: a)`, holding [`RonValue::Unit`],
+    /// [`RonValue::Tuple`] or [`RonValue::Struct`]
+    Named(String, Box<RonValue>),
+}
+
+/// Reads a RON document
+pub(crate) fn parse(data: &[u8]) -> Result<RonValue, Error> {
+    let source = std::str::from_utf8(data).map_err(|e| Error {
+        code: ErrorCode::Utf8Error(e),
+        position: Position { line: 0, col: 0 },
+    })?;
+    let mut parser = Parser { source, pos: 0 };
+
+    let value = parser.value()?;
+    parser.token(|de| de.end())?;
+
+    Ok(value)
+}
+
+/// Reads a RON document into `T`
+pub(crate) fn from_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    let document = parse(data)?;
+    T::deserialize(&document)
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    /// Reads a token with a `ron` deserializer over the rest of the source,
+    /// which skips whitespace and comments first
+    fn token<T>(
+        &mut self,
+        read: impl FnOnce(&mut RonDeserializer<'s>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let rest = &self.source[self.pos..];
+        let mut de = RonDeserializer::from_str(rest).map_err(|e| self.locate(e))?;
+        let token = read(&mut de).map_err(|e| self.locate(e))?;
+
+        self.pos += rest.len() - de.remainder().len();
+        Ok(token)
+    }
+
+    /// Moves the position of an error from `ron`, which starts at the
+    /// current token, to the whole source
+    fn locate(&self, mut error: Error) -> Error {
+        let Position { line, col } = error.position;
+        if line == 0 {
+            return error;
+        }
+
+        let before = &self.source[..self.pos];
+        let start_col = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+        error.position = Position {
+            line: line + before.matches('\n').count(),
+            col: if line == 1 { start_col + col - 1 } else { col },
+        };
+        error
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        self.locate(Error {
+            code,
+            position: Position { line: 1, col: 1 },
+        })
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        self.token(|_| Ok(()))?;
+        Ok(self.source.as_bytes().get(self.pos).copied())
+    }
+
+    fn consume(&mut self, expected: u8) -> Result<bool, Error> {
+        let found = self.peek()? == Some(expected);
+        if found {
+            self.pos += 1;
+        }
+        Ok(found)
+    }
+
+    fn expect(&mut self, expected: u8, code: ErrorCode) -> Result<(), Error> {
+        if self.consume(expected)? {
+            Ok(())
+        } else {
+            Err(self.error(code))
+        }
+    }
+
+    /// An identifier, or `None` if the next token isn't one
+    fn ident(&mut self) -> Result<Option<String>, Error> {
+        match self.peek()? {
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' => {}
+            _ => return Ok(None),
+        }
+
+        // `r"..."` fails as an identifier, leaving it to be read as a string
+        let start = self.pos;
+        match self.token(|de| de.deserialize_identifier(TokenVisitor)) {
+            Ok(RonValue::String(ident)) => Ok(Some(ident)),
+            _ => {
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<RonValue, Error> {
+        if let Some(ident) = self.ident()? {
+            return self.named(ident);
+        }
+
+        match self.peek()? {
+            Some(b'(') => self.parenthesized(),
+            Some(b'[') => {
+                let mut values = Vec::new();
+                self.pos += 1;
+                self.items(b']', ErrorCode::ExpectedArrayEnd, |parser| {
+                    values.push(parser.value()?);
+                    Ok(())
+                })?;
+                Ok(RonValue::List(values))
+            }
+            Some(b'{') => {
+                let mut entries = Vec::new();
+                self.pos += 1;
+                self.items(b'}', ErrorCode::ExpectedMapEnd, |parser| {
+                    let key = parser.value()?;
+                    parser.expect(b':', ErrorCode::ExpectedMapColon)?;
+                    entries.push((key, parser.value()?));
+                    Ok(())
+                })?;
+                Ok(RonValue::Map(entries))
+            }
+            // Strings, chars and numbers
+            _ => self.token(|de| de.deserialize_any(TokenVisitor)),
+        }
+    }
+
+    /// `true`, `false`, `None`, `Some(value)`, `inf`, `NaN` or a named value
+    fn named(&mut self, ident: String) -> Result<RonValue, Error> {
+        match ident.as_str() {
+            "true" => return Ok(RonValue::Bool(true)),
+            "false" => return Ok(RonValue::Bool(false)),
+            "None" => return Ok(RonValue::Option(None)),
+            "inf" => return Ok(RonValue::Float(f64::INFINITY)),
+            "NaN" => return Ok(RonValue::Float(f64::NAN)),
+            "Some" => {
+                self.expect(b'(', ErrorCode::ExpectedOption)?;
+                let value = self.value()?;
+                self.consume(b',')?;
+                self.expect(b')', ErrorCode::ExpectedOptionEnd)?;
+                return Ok(RonValue::Option(Some(Box::new(value))));
+            }
+            _ => {}
+        }
+
+        let content = if self.peek()? == Some(b'(') {
+            self.parenthesized()?
+        } else {
+            RonValue::Unit
+        };
+        Ok(RonValue::Named(ident, Box::new(content)))
+    }
+
+    /// `()`, `(a, b)` or `(field: a)`
+    fn parenthesized(&mut self) -> Result<RonValue, Error> {
+        self.expect(b'(', ErrorCode::ExpectedStruct)?;
+        if self.consume(b')')? {
+            return Ok(RonValue::Unit);
+        }
+
+        let start = self.pos;
+        let is_struct = self.ident()?.is_some() && self.consume(b':')?;
+        self.pos = start;
+
+        if is_struct {
+            let mut fields = Vec::new();
+            self.items(b')', ErrorCode::ExpectedStructEnd, |parser| {
+                let field = parser
+                    .ident()?
+                    .ok_or_else(|| parser.error(ErrorCode::ExpectedIdentifier))?;
+                parser.expect(b':', ErrorCode::ExpectedMapColon)?;
+                fields.push((field, parser.value()?));
+                Ok(())
+            })?;
+            Ok(RonValue::Struct(fields))
+        } else {
+            let mut values = Vec::new();
+            self.items(b')', ErrorCode::ExpectedStructEnd, |parser| {
+                values.push(parser.value()?);
+                Ok(())
+            })?;
+            Ok(RonValue::Tuple(values))
+        }
+    }
+
+    /// Comma separated items up to the `close` delimiter, after the opening
+    /// one, allowing a trailing comma
+    fn items(
+        &mut self,
+        close: u8,
+        code: ErrorCode,
+        mut item: impl FnMut(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        loop {
+            if self.consume(close)? {
+                return Ok(());
+            }
+
+            item(self)?;
+
+            if !self.consume(b',')? {
+                return self.expect(close, code);
+            }
+        }
+    }
+}
+
+/// Identifiers, strings, chars and numbers read by `ron`
+struct TokenVisitor;
+
+impl<'de> Visitor<'de> for TokenVisitor {
+    type Value = RonValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string, char or number")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<RonValue, E> {
+        Ok(RonValue::String(v.to_string()))
+    }
+
+    fn visit_char<E>(self, v: char) -> Result<RonValue, E> {
+        Ok(RonValue::Char(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<RonValue, E> {
+        Ok(RonValue::Int(v.into()))
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<RonValue, E> {
+        Ok(RonValue::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<RonValue, E> {
+        Ok(RonValue::Int(v.into()))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<RonValue, E> {
+        i128::try_from(v)
+            .map(RonValue::Int)
+            .map_err(|_| E::custom(format!("{} is out of range", v)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<RonValue, E> {
+        Ok(RonValue::Float(v))
+    }
+}
+
+impl RonValue {
+    /// The value without its name, for types that don't care about it
+    fn unnamed(&self) -> &RonValue {
+        match self {
+            RonValue::Named(_, content) => content,
+            value => value,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de RonValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for &'de RonValue {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RonValue::Bool(v) => visitor.visit_bool(*v),
+            RonValue::Char(v) => visitor.visit_char(*v),
+            RonValue::String(v) => visitor.visit_borrowed_str(v),
+            RonValue::Int(v) => {
+                if let Ok(v) = i64::try_from(*v) {
+                    visitor.visit_i64(v)
+                } else if let Ok(v) = u64::try_from(*v) {
+                    visitor.visit_u64(v)
+                } else {
+                    visitor.visit_i128(*v)
+                }
+            }
+            RonValue::Float(v) => visitor.visit_f64(*v),
+            RonValue::Unit => visitor.visit_unit(),
+            RonValue::Option(None) => visitor.visit_none(),
+            RonValue::Option(Some(value)) => visitor.visit_some(&**value),
+            RonValue::List(values) | RonValue::Tuple(values) => {
+                visitor.visit_seq(SeqDeserializer::new(values.iter()))
+            }
+            RonValue::Map(entries) => visitor.visit_map(MapDeserializer::new(
+                entries.iter().map(|(key, value)| (key, value)),
+            )),
+            RonValue::Struct(fields) => visitor.visit_map(MapDeserializer::new(
+                fields.iter().map(|(name, value)| (name.as_str(), value)),
+            )),
+            RonValue::Named(..) => visitor.visit_enum(self),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RonValue::Option(None) => visitor.visit_none(),
+            RonValue::Option(Some(value)) => visitor.visit_some(&**value),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.unnamed() {
+            RonValue::Tuple(values) if values.len() == 1 => {
+                visitor.visit_newtype_struct(&values[0])
+            }
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            RonValue::Named(..) => visitor.visit_enum(self),
+            RonValue::String(variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.unnamed().deserialize_any(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.unnamed().deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.unnamed().deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.unnamed().deserialize_any(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.unnamed().deserialize_any(visitor)
+    }
+
+    /// `()` is a struct without any field given
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.unnamed() {
+            RonValue::Unit => visitor.visit_map(MapDeserializer::new(std::iter::empty::<(
+                &RonValue,
+                &RonValue,
+            )>())),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit identifier ignored_any
+    }
+}
+
+impl<'de> EnumAccess<'de> for &'de RonValue {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self {
+            RonValue::Named(name, content) => {
+                let variant =
+                    seed.deserialize(IntoDeserializer::<Error>::into_deserializer(name.as_str()))?;
+                Ok((variant, &**content))
+            }
+            _ => Err(de::Error::custom("expected a named value")),
+        }
+    }
+}
+
+/// Content of a named value
+impl<'de> VariantAccess<'de> for &'de RonValue {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self {
+            RonValue::Unit => Ok(()),
+            _ => Err(de::Error::custom("expected a unit variant")),
+        }
+    }
+
+    /// The content as written, `(a)` is read as a tuple of one value
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::{from_bytes, parse, RonValue};
+
+    fn error(source: &str) -> String {
+        parse(source.as_bytes()).unwrap_err().to_string()
+    }
+
+    fn named(name: &str, content: RonValue) -> RonValue {
+        RonValue::Named(name.to_string(), Box::new(content))
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Point {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Attack {
+        Scratch,
+        Breath { range: u8 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Sample {
+        flag: bool,
+        count: u8,
+        big: i64,
+        hex: u32,
+        ratio: f32,
+        precise: f64,
+        name: String,
+        escaped: String,
+        raw: String,
+        letter: char,
+        list: Vec<i32>,
+        pair: (u8, String),
+        map: BTreeMap<String, f64>,
+        maybe: Option<u8>,
+        nothing: Option<u8>,
+        unit: (),
+        point: Point,
+        attacks: Vec<Attack>,
+    }
+
+    #[test]
+    fn test_matches_ron() {
+        let source = r##"
+            #![enable(unwrap_newtypes)]
+            // Line comment
+            Sample(
+                flag: true,
+                count: 255,
+                big: -9_000_000_000,
+                hex: 0xff,
+                ratio: 0.5,
+                precise: 0.1, /* block /* nested */ comment */
+                name: "Rex",
+                escaped: "quote \" tab \t \u{e9}",
+                raw: r#"C:\path "quoted""#,
+                letter: 'é',
+                list: [1, -2, +3,],
+                pair: (1, "one"),
+                map: { "a": 1.0, "b": -inf },
+                maybe: Some(1),
+                nothing: None,
+                unit: (),
+                point: Point(x: 1, y: .5),
+                attacks: [Scratch, Breath(range: 4)],
+            )
+        "##;
+
+        let ours: Sample = from_bytes(source.as_bytes()).unwrap();
+        let theirs: Sample = ron::de::from_str(source).unwrap();
+        assert_eq!(ours, theirs);
+    }
+
+    #[test]
+    fn test_named_values() {
+        assert_eq!(
+            parse(b"Transform(x: 1, y: 2.5)").unwrap(),
+            named(
+                "Transform",
+                RonValue::Struct(vec![
+                    ("x".to_string(), RonValue::Int(1)),
+                    ("y".to_string(), RonValue::Float(2.5)),
+                ])
+            )
+        );
+        assert_eq!(
+            parse(b"[FireBreath, Bite (3), r#Move]").unwrap(),
+            RonValue::List(vec![
+                named("FireBreath", RonValue::Unit),
+                named("Bite", RonValue::Tuple(vec![RonValue::Int(3)])),
+                named("Move", RonValue::Unit),
+            ])
+        );
+        assert!(matches!(
+            parse(b"(Some(true), None, NaN)").unwrap(),
+            RonValue::Tuple(values) if matches!(
+                values.as_slice(),
+                [RonValue::Option(Some(_)), RonValue::Option(None), RonValue::Float(v)] if v.is_nan()
+            )
+        ));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eq!(error("(x: 1,\n y 2)"), "2:4: Expected colon");
+        assert_eq!(error("[1 2]"), "1:4: Expected end of array");
+        assert_eq!(error("{ \"a\": @ }"), "1:8: Unexpected byte");
+        assert_eq!(
+            error("  \n  1 2"),
+            "2:5: Non-whitespace trailing characters"
+        );
+        assert_eq!(error("[\"é\", x:]"), "1:9: Expected end of array");
+        assert_eq!(error("(a: 1, 2)"), "1:8: Expected identifier");
+
+        // Errors from tokens read by `ron` are the same as its own
+        for source in [
+            "[1,\n  \"\\q\"]",
+            "[1, /* open",
+            "{\n \"a\": 2e, }",
+            "#![enable(implicit_none)] 1",
+        ] {
+            let theirs = ron::de::from_str::<ron::Value>(source).unwrap_err();
+            assert_eq!(error(source), theirs.to_string(), "{}", source);
+        }
+    }
+}
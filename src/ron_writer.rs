@@ -0,0 +1,122 @@
+//! RON writer, the counterpart of [`ron_reader`](crate::ron_reader)
+//!
+//! Values are written by `ron`'s pretty printer. `ron` only knows the names
+//! of structs when serializing them, so values are first serialized into a
+//! [`Value`] tree, where the `$named` calls named values are wrapped in are
+//! read back as [`Value::Named`]. The tree is written with those as structs,
+//! `Transform(x: 1.0)`, and everything else as maps and lists.
+
+use std::{cell::RefCell, collections::HashSet};
+
+use ron::{
+    ser::{PrettyConfig, Serializer as RonSerializer},
+    Error,
+};
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeTupleStruct, Serializer,
+};
+
+use crate::value::{serialize_func, widen_f32, Value, ValueSerializer};
+
+/// Writes `value` as pretty-printed RON
+pub(crate) fn to_string_pretty<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    let value = value
+        .serialize(ValueSerializer {
+            struct_names: false,
+        })
+        .map_err(<Error as ser::Error>::custom)?;
+    let config = PrettyConfig::new().with_decimal_floats(true);
+
+    let mut out = Vec::new();
+    Ron(&value).serialize(&mut RonSerializer::new(&mut out, Some(config), true)?)?;
+    out.push(b'\n');
+
+    String::from_utf8(out).map_err(<Error as ser::Error>::custom)
+}
+
+/// Whether `s` can be written as a field or struct name, and is not read
+/// back as a value like `true` or `None`
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(s, "true" | "false" | "None" | "Some" | "inf" | "NaN")
+}
+
+/// `ron` takes struct and field names as `&'static str`, the few names
+/// blueprints use are leaked once per thread
+fn intern(name: &str) -> &'static str {
+    thread_local! {
+        static NAMES: RefCell<HashSet<&'static str>> = RefCell::default();
+    }
+
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        match names.get(name) {
+            Some(&name) => name,
+            None => {
+                let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                names.insert(name);
+                name
+            }
+        }
+    })
+}
+
+/// A [`Value`] written with its named values as RON structs
+struct Ron<'a>(&'a Value);
+
+impl<'a> Serialize for Ron<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Value::Named(name, content) => match &**content {
+                Value::Unit if is_ident(name) => serializer.serialize_unit_struct(intern(name)),
+                Value::Vec(values) if is_ident(name) && !values.is_empty() => {
+                    let mut tuple =
+                        serializer.serialize_tuple_struct(intern(name), values.len())?;
+                    for value in values {
+                        tuple.serialize_field(&Ron(value))?;
+                    }
+                    tuple.end()
+                }
+                Value::KeyMap(fields)
+                    if is_ident(name) && fields.keys().all(|field| is_ident(field)) =>
+                {
+                    let mut fields_out = serializer.serialize_struct(intern(name), fields.len())?;
+                    for (field, value) in fields {
+                        fields_out.serialize_field(intern(field), &Ron(value))?;
+                    }
+                    fields_out.end()
+                }
+                // Names and fields that can't be written in RON are kept in
+                // a call
+                Value::Unit => serialize_func(serializer, "named", name),
+                content => serialize_func(serializer, "named", &(name, Ron(content))),
+            },
+            Value::KeyMap(map) => {
+                let mut map_out = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    map_out.serialize_entry(key, &Ron(value))?;
+                }
+                map_out.end()
+            }
+            Value::Vec(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(&Ron(value))?;
+                }
+                seq.end()
+            }
+            Value::Option(Some(value)) => serializer.serialize_some(&Ron(value)),
+            Value::F32(v) => serializer.serialize_f64(widen_f32(*v)),
+            Value::Entity(_) | Value::Blueprint(_) | Value::Asset(_) => {
+                let call = Value::from_component(self.0).map_err(ser::Error::custom)?;
+                Ron(&call).serialize(serializer)
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+#![allow(dead_code)]
+
+use crate::{expr::Environment, value::Value, Error};
+use rand::RngCore;
+use std::collections::HashMap;
+
+mod stdlib;
+
+type FuncImpl = Box<dyn Fn(&mut dyn RngCore, &[Value]) -> Result<Value, Error> + Send + Sync>;
+
+struct Function {
+    func_impl: FuncImpl,
+}
+
+impl Function {
+    fn new<F>(f: F) -> Self
+    where
+        F: Fn(&mut dyn RngCore, &[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        Function {
+            func_impl: Box::new(f),
+        }
+    }
+
+    fn eval(&self, rng: &mut dyn RngCore, args: &[Value]) -> Result<Value, Error> {
+        (self.func_impl)(rng, args)
+    }
+}
+
+#[derive(Default)]
+pub struct SimpleRuntime {
+    functions: HashMap<String, Function>,
+}
+
+impl SimpleRuntime {
+    pub fn new() -> Self {
+        SimpleRuntime {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Runtime with the standard functions registered
+    pub fn with_stdlib() -> Self {
+        let mut runtime = SimpleRuntime::new();
+        stdlib::register_stdlib(&mut runtime);
+        runtime
+    }
+
+    pub fn register_func<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        self.functions
+            .insert(name.to_string(), Function::new(move |_rng, args| f(args)));
+    }
+
+    /// Register a function drawing from the evaluation's seeded random number generator
+    pub fn register_rand_func<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&mut dyn RngCore, &[Value]) -> Result<Value, Error> + Send + Sync + 'static,
+    {
+        self.functions.insert(name.to_string(), Function::new(f));
+    }
+}
+
+impl Environment for SimpleRuntime {
+    fn eval_func(&self, rng: &mut dyn RngCore, name: &str, args: &[Value]) -> Result<Value, Error> {
+        let func = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::UndefinedFunctionError(name.to_string()))?;
+        func.eval(rng, args)
+    }
+}
@@ -0,0 +1,604 @@
+//! Standard functions available to every blueprint
+
+use std::convert::TryFrom;
+
+use bevy::math::{EulerRot, Quat};
+use rand::{Rng, RngCore};
+
+use crate::{runtime::SimpleRuntime, value::Value, Error};
+
+pub(crate) fn register_stdlib(runtime: &mut SimpleRuntime) {
+    runtime.register_func("add", add);
+    runtime.register_func("mul", mul);
+    runtime.register_func("clamp", clamp);
+    runtime.register_func("lerp", lerp);
+
+    runtime.register_func("concat", concat);
+    runtime.register_func("format", format);
+    runtime.register_func("upper", upper);
+
+    runtime.register_func("len", len);
+    runtime.register_func("concat_list", concat_list);
+    runtime.register_func("repeat", repeat);
+
+    runtime.register_func("vec2", vec2);
+    runtime.register_func("vec3", vec3);
+    runtime.register_func("quat_from_euler", quat_from_euler);
+
+    runtime.register_rand_func("rand", rand_unit);
+    runtime.register_rand_func("rand_range", rand_range);
+    runtime.register_rand_func("choose", choose);
+}
+
+fn invalid_args(name: &str, reason: impl Into<String>) -> Error {
+    Error::InvalidArguments(name.to_string(), reason.into())
+}
+
+fn expect_arg_count(name: &str, args: &[Value], count: usize) -> Result<(), Error> {
+    if args.len() != count {
+        return Err(invalid_args(
+            name,
+            format!("expected {} arguments, got {}", count, args.len()),
+        ));
+    }
+    Ok(())
+}
+
+fn to_f32(value: &Value) -> Result<f32, Error> {
+    match value {
+        Value::F32(v) => Ok(*v),
+        Value::I32(v) => Ok(*v as f32),
+        Value::I64(v) => Ok(*v as f32),
+        Value::U64(v) => Ok(*v as f32),
+        Value::F64(v) => Ok(*v as f32),
+        unexpected => Err(Error::UnexpectedType {
+            unexpected: unexpected.typename(),
+            expected: "number",
+        }),
+    }
+}
+
+/// Integer values of `args` if every argument is an integer
+fn all_i32(args: &[Value]) -> Option<Vec<i32>> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::I32(v) => Some(*v),
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_display_string(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Bool(v) => Ok(v.to_string()),
+        value if value.is_number() => Ok(value.number_string()),
+        unexpected => Err(Error::UnexpectedType {
+            unexpected: unexpected.typename(),
+            expected: "string, bool or number",
+        }),
+    }
+}
+
+fn fold_numbers(
+    name: &str,
+    args: &[Value],
+    int_op: fn(i32, i32) -> Option<i32>,
+    float_op: fn(f32, f32) -> f32,
+) -> Result<Value, Error> {
+    let (first, rest) = args
+        .split_first()
+        .ok_or_else(|| invalid_args(name, "expected at least one argument"))?;
+
+    if let Some(ints) = all_i32(args) {
+        let mut acc = ints[0];
+        for v in &ints[1..] {
+            acc = int_op(acc, *v).ok_or_else(|| invalid_args(name, "integer overflow"))?;
+        }
+        return Ok(Value::I32(acc));
+    }
+
+    let mut acc = to_f32(first)?;
+    for v in rest {
+        acc = float_op(acc, to_f32(v)?);
+    }
+    Ok(Value::F32(acc))
+}
+
+/// `add(a, b, ...)`, integer if every argument is an integer
+fn add(args: &[Value]) -> Result<Value, Error> {
+    fold_numbers("add", args, i32::checked_add, |a, b| a + b)
+}
+
+/// `mul(a, b, ...)`, integer if every argument is an integer
+fn mul(args: &[Value]) -> Result<Value, Error> {
+    fold_numbers("mul", args, i32::checked_mul, |a, b| a * b)
+}
+
+/// `clamp(value, min, max)`
+fn clamp(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("clamp", args, 3)?;
+
+    if let Some(ints) = all_i32(args) {
+        if ints[1] > ints[2] {
+            return Err(invalid_args("clamp", "min is greater than max"));
+        }
+        return Ok(Value::I32(ints[0].clamp(ints[1], ints[2])));
+    }
+
+    let (value, min, max) = (to_f32(&args[0])?, to_f32(&args[1])?, to_f32(&args[2])?);
+
+    if min > max || min.is_nan() || max.is_nan() {
+        return Err(invalid_args("clamp", "min is greater than max"));
+    }
+
+    Ok(Value::F32(value.clamp(min, max)))
+}
+
+/// `lerp(a, b, t)`
+fn lerp(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("lerp", args, 3)?;
+
+    let (a, b, t) = (to_f32(&args[0])?, to_f32(&args[1])?, to_f32(&args[2])?);
+
+    Ok(Value::F32(a + (b - a) * t))
+}
+
+/// `concat(a, b, ...)` of strings and numbers
+fn concat(args: &[Value]) -> Result<Value, Error> {
+    Ok(Value::String(
+        args.iter()
+            .map(to_display_string)
+            .collect::<Result<String, Error>>()?,
+    ))
+}
+
+/// `format(template, args...)` replacing each `{}` in order, `{{` and `}}` escape braces
+fn format(args: &[Value]) -> Result<Value, Error> {
+    let (template, mut rest) = match args.split_first() {
+        Some((Value::String(template), rest)) => (template, rest.iter()),
+        Some((unexpected, _)) => {
+            return Err(Error::UnexpectedType {
+                unexpected: unexpected.typename(),
+                expected: "string",
+            })
+        }
+        None => return Err(invalid_args("format", "expected a template")),
+    };
+
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                output.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                let arg = rest
+                    .next()
+                    .ok_or_else(|| invalid_args("format", "not enough arguments"))?;
+                output.push_str(&to_display_string(arg)?);
+            }
+            ('{', _) | ('}', _) => {
+                return Err(invalid_args("format", format!("unmatched `{}`", c)));
+            }
+            _ => output.push(c),
+        }
+    }
+
+    if rest.next().is_some() {
+        return Err(invalid_args("format", "too many arguments"));
+    }
+
+    Ok(Value::String(output))
+}
+
+/// `upper(s)`
+fn upper(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("upper", args, 1)?;
+
+    match &args[0] {
+        Value::String(s) => Ok(Value::String(s.to_uppercase())),
+        unexpected => Err(Error::UnexpectedType {
+            unexpected: unexpected.typename(),
+            expected: "string",
+        }),
+    }
+}
+
+/// `len(list)`, also accepts strings and maps
+fn len(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("len", args, 1)?;
+
+    let len = match &args[0] {
+        Value::Vec(values) => values.len(),
+        Value::String(s) => s.chars().count(),
+        Value::KeyMap(m) => m.len(),
+        unexpected => {
+            return Err(Error::UnexpectedType {
+                unexpected: unexpected.typename(),
+                expected: "vec",
+            })
+        }
+    };
+
+    i32::try_from(len)
+        .map(Value::I32)
+        .map_err(|_| invalid_args("len", "length does not fit in i32"))
+}
+
+/// `concat_list(a, b, ...)` of lists
+fn concat_list(args: &[Value]) -> Result<Value, Error> {
+    let mut output = Vec::new();
+
+    for arg in args {
+        match arg {
+            Value::Vec(values) => output.extend(values.iter().cloned()),
+            unexpected => {
+                return Err(Error::UnexpectedType {
+                    unexpected: unexpected.typename(),
+                    expected: "vec",
+                })
+            }
+        }
+    }
+
+    Ok(Value::Vec(output))
+}
+
+/// `repeat(value, count)`
+fn repeat(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("repeat", args, 2)?;
+
+    let count = match &args[1] {
+        Value::I32(count) => usize::try_from(*count)
+            .map_err(|_| invalid_args("repeat", "count must not be negative"))?,
+        unexpected => {
+            return Err(Error::UnexpectedType {
+                unexpected: unexpected.typename(),
+                expected: "i32",
+            })
+        }
+    };
+
+    Ok(Value::Vec(vec![args[0].clone(); count]))
+}
+
+fn float_vec(name: &str, args: &[Value], count: usize) -> Result<Value, Error> {
+    expect_arg_count(name, args, count)?;
+
+    Ok(Value::Vec(
+        args.iter()
+            .map(|arg| to_f32(arg).map(Value::F32))
+            .collect::<Result<_, Error>>()?,
+    ))
+}
+
+/// `vec2(x, y)`, deserializes into a `Vec2`
+fn vec2(args: &[Value]) -> Result<Value, Error> {
+    float_vec("vec2", args, 2)
+}
+
+/// `vec3(x, y, z)`, deserializes into a `Vec3`
+fn vec3(args: &[Value]) -> Result<Value, Error> {
+    float_vec("vec3", args, 3)
+}
+
+/// `quat_from_euler(x, y, z)` with angles in radians applied in XYZ order,
+/// deserializes into a `Quat`
+fn quat_from_euler(args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("quat_from_euler", args, 3)?;
+
+    let (x, y, z) = (to_f32(&args[0])?, to_f32(&args[1])?, to_f32(&args[2])?);
+    let quat = Quat::from_euler(EulerRot::XYZ, x, y, z);
+
+    Ok(Value::Vec(
+        quat.to_array().iter().copied().map(Value::F32).collect(),
+    ))
+}
+
+/// `rand()` between 0 inclusive and 1 exclusive
+fn rand_unit(rng: &mut dyn RngCore, args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("rand", args, 0)?;
+
+    Ok(Value::F32(rng.gen()))
+}
+
+/// `rand_range(min, max)`, integers include `max` while floats exclude it
+fn rand_range(rng: &mut dyn RngCore, args: &[Value]) -> Result<Value, Error> {
+    expect_arg_count("rand_range", args, 2)?;
+
+    if let Some(ints) = all_i32(args) {
+        if ints[0] > ints[1] {
+            return Err(invalid_args("rand_range", "min is greater than max"));
+        }
+        return Ok(Value::I32(rng.gen_range(ints[0]..=ints[1])));
+    }
+
+    let (min, max) = (to_f32(&args[0])?, to_f32(&args[1])?);
+
+    // Also catches f64 bounds too large for an f32
+    if !(min.is_finite() && max.is_finite() && (max - min).is_finite()) {
+        return Err(invalid_args("rand_range", "the range is not finite"));
+    }
+
+    if min == max {
+        Ok(Value::F32(min))
+    } else if min < max {
+        Ok(Value::F32(rng.gen_range(min..max)))
+    } else {
+        Err(invalid_args("rand_range", "min is greater than max"))
+    }
+}
+
+/// `choose(list)` or `choose(a, b, ...)` picks one element uniformly
+fn choose(rng: &mut dyn RngCore, args: &[Value]) -> Result<Value, Error> {
+    let options = match args {
+        [Value::Vec(options)] => options.as_slice(),
+        options => options,
+    };
+
+    if options.is_empty() {
+        return Err(invalid_args("choose", "nothing to choose from"));
+    }
+
+    Ok(options[rng.gen_range(0..options.len())].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        math::{EulerRot, Quat, Vec2, Vec3},
+        transform::components::Transform,
+    };
+    use serde::Deserialize;
+
+    use crate::{
+        expr::{Context, Evaluatable, Expr},
+        runtime::SimpleRuntime,
+        value::Value,
+        Error,
+    };
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let runtime = SimpleRuntime::with_stdlib();
+        let expr = Expr::Func(
+            name.to_string(),
+            args.into_iter().map(Expr::Constant).collect(),
+        );
+
+        expr.eval(&Context::new(&runtime))
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            call("add", vec![Value::I32(40), Value::I32(2)]).unwrap(),
+            Value::I32(42)
+        );
+        assert_eq!(
+            call("add", vec![Value::I32(40), Value::F32(2.5)]).unwrap(),
+            Value::F32(42.5)
+        );
+        assert_eq!(
+            call("mul", vec![Value::I32(6), Value::I32(7)]).unwrap(),
+            Value::I32(42)
+        );
+        assert_eq!(
+            call(
+                "clamp",
+                vec![Value::I32(150), Value::I32(0), Value::I32(100)]
+            )
+            .unwrap(),
+            Value::I32(100)
+        );
+        assert_eq!(
+            call(
+                "clamp",
+                vec![Value::F32(-0.5), Value::F32(0.0), Value::I32(1)]
+            )
+            .unwrap(),
+            Value::F32(0.0)
+        );
+        assert_eq!(
+            call(
+                "lerp",
+                vec![Value::F32(10.0), Value::F32(20.0), Value::F32(0.25)]
+            )
+            .unwrap(),
+            Value::F32(12.5)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        assert!(matches!(
+            call("add", vec![Value::I32(i32::MAX), Value::I32(1)]),
+            Err(Error::InvalidArguments(..))
+        ));
+        assert!(matches!(
+            call("clamp", vec![Value::I32(1), Value::I32(2)]),
+            Err(Error::InvalidArguments(..))
+        ));
+        assert!(matches!(
+            call("mul", vec![Value::F32(1.0), string("two")]),
+            Err(Error::UnexpectedType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            call("concat", vec![string("corgi "), Value::I32(2)]).unwrap(),
+            string("corgi 2")
+        );
+        assert_eq!(
+            call(
+                "format",
+                vec![
+                    string("{} has {} hp {{max}}"),
+                    string("corgi"),
+                    Value::I32(150)
+                ]
+            )
+            .unwrap(),
+            string("corgi has 150 hp {max}")
+        );
+        assert!(matches!(
+            call("format", vec![string("{} and {}"), string("corgi")]),
+            Err(Error::InvalidArguments(..))
+        ));
+        assert_eq!(
+            call("upper", vec![string("corgi")]).unwrap(),
+            string("CORGI")
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let list = Value::Vec(vec![string("Scratch"), string("Bark")]);
+
+        assert_eq!(call("len", vec![list.clone()]).unwrap(), Value::I32(2));
+        assert_eq!(
+            call(
+                "concat_list",
+                vec![list.clone(), Value::Vec(vec![string("FireBreath")])]
+            )
+            .unwrap(),
+            Value::Vec(vec![
+                string("Scratch"),
+                string("Bark"),
+                string("FireBreath")
+            ])
+        );
+        assert_eq!(
+            call("repeat", vec![Value::I32(0), Value::I32(3)]).unwrap(),
+            Value::Vec(vec![Value::I32(0); 3])
+        );
+        assert!(matches!(
+            call("repeat", vec![Value::I32(0), Value::I32(-1)]),
+            Err(Error::InvalidArguments(..))
+        ));
+    }
+
+    #[test]
+    fn test_random() {
+        for _ in 0..100 {
+            match call("rand_range", vec![Value::I32(80), Value::I32(120)]).unwrap() {
+                Value::I32(v) => assert!((80..=120).contains(&v)),
+                unexpected => panic!("Expected i32, got {:?}", unexpected),
+            }
+
+            match call("rand_range", vec![Value::F32(-1.0), Value::I32(1)]).unwrap() {
+                Value::F32(v) => assert!((-1.0..1.0).contains(&v)),
+                unexpected => panic!("Expected f32, got {:?}", unexpected),
+            }
+
+            match call("rand", Vec::new()).unwrap() {
+                Value::F32(v) => assert!((0.0..1.0).contains(&v)),
+                unexpected => panic!("Expected f32, got {:?}", unexpected),
+            }
+
+            let attack = call(
+                "choose",
+                vec![Value::Vec(vec![string("Scratch"), string("Bark")])],
+            )
+            .unwrap();
+            assert!(attack == string("Scratch") || attack == string("Bark"));
+        }
+
+        assert_eq!(
+            call("choose", vec![string("Bark")]).unwrap(),
+            string("Bark")
+        );
+        assert!(matches!(
+            call("choose", vec![Value::Vec(Vec::new())]),
+            Err(Error::InvalidArguments(..))
+        ));
+        assert!(matches!(
+            call("rand_range", vec![Value::I32(2), Value::I32(1)]),
+            Err(Error::InvalidArguments(..))
+        ));
+
+        for (min, max) in [
+            (Value::I32(0), Value::F32(f32::INFINITY)),
+            (Value::F32(f32::NAN), Value::I32(1)),
+            (Value::F32(-3e38), Value::F32(3e38)),
+            (Value::I32(0), Value::F64(1e300)),
+        ] {
+            assert!(matches!(
+                call("rand_range", vec![min, max]),
+                Err(Error::InvalidArguments(..))
+            ));
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct TestTransform {
+        translation: Vec3,
+        rotation: Quat,
+        scale: Vec3,
+    }
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(
+            call("vec2", vec![Value::I32(1), Value::F32(2.0)])
+                .unwrap()
+                .to_component::<Vec2>()
+                .unwrap(),
+            Vec2::new(1.0, 2.0)
+        );
+
+        let translation = call(
+            "vec3",
+            vec![Value::I32(1), Value::F32(0.0), Value::F32(0.0)],
+        )
+        .unwrap();
+        let rotation = call(
+            "quat_from_euler",
+            vec![
+                Value::F32(0.0),
+                Value::F32(std::f32::consts::FRAC_PI_2),
+                Value::I32(0),
+            ],
+        )
+        .unwrap();
+        let scale = call("vec3", vec![Value::I32(1), Value::I32(1), Value::I32(1)]).unwrap();
+
+        let transform = Value::KeyMap(
+            vec![
+                ("translation".to_string(), translation),
+                ("rotation".to_string(), rotation),
+                ("scale".to_string(), scale),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .to_component::<TestTransform>()
+        .unwrap();
+
+        let transform = Transform {
+            translation: transform.translation,
+            rotation: transform.rotation,
+            scale: transform.scale,
+        };
+
+        assert_eq!(
+            transform,
+            Transform::from_xyz(1.0, 0.0, 0.0).with_rotation(Quat::from_euler(
+                EulerRot::XYZ,
+                0.0,
+                std::f32::consts::FRAC_PI_2,
+                0.0
+            ))
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use super::Value;
+use super::{FromNumber, Value};
 use serde::{
-    de::value::{MapDeserializer, SeqDeserializer},
-    forward_to_deserialize_any,
+    de::{
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
 };
 
 pub type Error = serde::de::value::Error;
@@ -10,6 +13,51 @@ pub(crate) struct ValueDeserializer<'a> {
     pub value: &'a Value,
 }
 
+impl<'de> ValueDeserializer<'de> {
+    fn number<T: FromNumber>(&self) -> Result<Option<T>, Error> {
+        if !self.value.is_number() {
+            return Ok(None);
+        }
+
+        self.value.to_number().map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "{} is out of range for {}",
+                self.value.number_string(),
+                T::NAME
+            ))
+        })
+    }
+
+    /// Content of a named struct, which has to be named after its type
+    fn unnamed(self, expected: &str) -> Result<Self, Error> {
+        match self.value {
+            Value::Named(name, value) if name == expected => Ok(ValueDeserializer { value }),
+            Value::Named(name, _) => Err(serde::de::Error::custom(format!(
+                "`{}` given for a `{}`",
+                name, expected
+            ))),
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Numbers are converted to the type being deserialized
+macro_rules! deserialize_number {
+    ($($deserialize:ident => $visit:ident,)*) => {
+        $(
+            fn $deserialize<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                match self.number()? {
+                    Some(v) => visitor.$visit(v),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
 impl<'a> serde::de::IntoDeserializer<'a> for ValueDeserializer<'a> {
     type Deserializer = Self;
 
@@ -26,9 +74,18 @@ impl<'de> serde::Deserializer<'de> for ValueDeserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         match self.value {
-            Value::String(val) => visitor.visit_str(val.as_str()),
+            Value::String(val) | Value::Blueprint(val) | Value::Asset(val) => {
+                visitor.visit_str(val.as_str())
+            }
+            Value::Bool(val) => visitor.visit_bool(*val),
             Value::I32(val) => visitor.visit_i32(*val),
+            Value::I64(val) => visitor.visit_i64(*val),
+            Value::U64(val) => visitor.visit_u64(*val),
             Value::F32(val) => visitor.visit_f32(*val),
+            Value::F64(val) => visitor.visit_f64(*val),
+            Value::Unit => visitor.visit_unit(),
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(value)) => visitor.visit_some(ValueDeserializer { value }),
             Value::Vec(values) => visitor.visit_seq(SeqDeserializer::new(
                 values.iter().map(|value| ValueDeserializer { value }),
             )),
@@ -36,15 +93,171 @@ impl<'de> serde::Deserializer<'de> for ValueDeserializer<'de> {
                 map.iter()
                     .map(|(key, value)| (key.as_str(), ValueDeserializer { value })),
             )),
-            Value::Entity(_comp_map) => {
-                unimplemented!()
-            }
+            Value::Entity(entity) => visitor.visit_map(MapDeserializer::new(
+                entity
+                    .components()
+                    .map(|(name, value)| (name, ValueDeserializer { value })),
+            )),
+            // Types read from any value have no name to check
+            Value::Named(_, value) => ValueDeserializer { value }.deserialize_any(visitor),
         }
     }
 
+    /// Values that are not options are taken as present
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self.value {
+            Value::Option(None) => visitor.visit_none(),
+            Value::Option(Some(value)) => visitor.visit_some(ValueDeserializer { value }),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    /// Variants are named values like `Bark`, `Bite(3)` or `Breath(range: 4)`,
+    /// unit variants can also be written as a string
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self.value {
+            Value::String(variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            Value::Named(name, value) => visitor.visit_enum(VariantDeserializer { name, value }),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.unnamed(name)?.deserialize_any(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.unnamed(name)?.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.unnamed(name)?.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.unnamed(name)?.deserialize_any(visitor)
+    }
+
+    deserialize_number! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        bool i128 u128 char str string
+        bytes byte_buf unit seq tuple map identifier ignored_any
+    }
+}
+
+/// Enum variant from a [`Value::Named`]
+struct VariantDeserializer<'a> {
+    name: &'a str,
+    value: &'a Value,
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer<'de> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.name.into_deserializer())?;
+        Ok((variant, ValueDeserializer { value: self.value }))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.value {
+            Value::Unit => Ok(()),
+            value => Err(serde::de::Error::custom(format!(
+                "expected a unit variant, got {}",
+                value.typename()
+            ))),
+        }
+    }
+
+    /// `Bite(3)` holds a tuple of one value
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            Value::Vec(values) if values.len() == 1 => {
+                seed.deserialize(ValueDeserializer { value: &values[0] })
+            }
+            _ => seed.deserialize(self),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
     }
 }
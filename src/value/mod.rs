@@ -1,55 +1,141 @@
 //! Evaluated prints expressions
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::BTreeSet, iter::FromIterator};
 
 mod deser;
+mod number;
+mod ser;
+
+pub(crate) use number::{widen_f32, FromNumber};
+pub(crate) use ser::{serialize_func, serialize_named, ValueSerializer};
 
 pub use deser::Error as ToComponentError;
 
-#[derive(PartialEq, Debug, Clone, Default, Deserialize, Serialize)]
-#[serde(transparent)]
-pub struct EntityMap<T>(HashMap<String, T>);
+#[derive(PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(transparent, bound(deserialize = "T: Deserialize<'de>"))]
+pub struct EntityMap<T> {
+    /// Components in the order they were added, which is the order they are
+    /// inserted on the entity
+    components: IndexMap<String, T>,
+    /// Components taken off the entity, for blueprints dropping components
+    /// they inherit
+    #[serde(skip)]
+    removed: BTreeSet<String>,
+    /// Entities spawned as children of this one, `None` when they are not
+    /// listed, which is not the same as an empty `$children` when merging
+    #[serde(skip)]
+    children: Option<Vec<T>>,
+}
 
 impl<T> EntityMap<T> {
     pub fn new() -> Self {
-        EntityMap(HashMap::new())
+        EntityMap {
+            components: IndexMap::new(),
+            removed: BTreeSet::new(),
+            children: None,
+        }
     }
 
     pub fn add_component(&mut self, name: &str, value: T) {
-        self.0.insert(name.to_string(), value);
+        self.removed.remove(name);
+        self.components.insert(name.to_string(), value);
+    }
+
+    /// Drops component `name` and marks it to be removed from the entity
+    pub fn remove_component(&mut self, name: &str) {
+        self.components.shift_remove(name);
+        self.removed.insert(name.to_string());
+    }
+
+    pub fn get_component(&self, name: &str) -> Option<&T> {
+        self.components.get(name)
     }
 
     pub fn components(&self) -> impl Iterator<Item = (&str, &T)> {
-        self.0.iter().map(|(name, value)| (name.as_str(), value))
+        self.components
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Names of the components to remove from the entity, in sorted order
+    pub fn removed_components(&self) -> impl Iterator<Item = &str> {
+        self.removed.iter().map(String::as_str)
+    }
+
+    pub fn add_child(&mut self, child: T) {
+        self.children.get_or_insert_with(Vec::new).push(child);
+    }
+
+    /// Replaces the children of this entity with `children`, which lists them
+    /// even when empty
+    pub fn set_children(&mut self, children: Vec<T>) {
+        self.children = Some(children);
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &T> {
+        self.children.iter().flatten()
+    }
+
+    /// Whether children are listed, even if there are none, like with
+    /// `"$children": []`
+    pub fn lists_children(&self) -> bool {
+        self.children.is_some()
+    }
+
+    /// Takes the children out, leaving this entity without any
+    pub fn take_children(&mut self) -> Vec<T> {
+        self.children.take().unwrap_or_default()
     }
 
     pub fn into_components(self) -> impl Iterator<Item = (String, T)> {
-        self.0.into_iter()
+        self.components.into_iter()
     }
 
     pub fn try_map<F, U, E>(self, mut f: F) -> Result<EntityMap<U>, E>
     where
         F: FnMut(T) -> Result<U, E>,
     {
-        Ok(EntityMap(
-            self.0
+        Ok(EntityMap {
+            components: self
+                .components
                 .into_iter()
                 .map(|(name, comp)| Ok((name, f(comp)?)))
                 .collect::<Result<_, E>>()?,
-        ))
+            removed: self.removed,
+            children: self
+                .children
+                .map(|children| children.into_iter().map(f).collect::<Result<_, E>>())
+                .transpose()?,
+        })
     }
 
     pub fn map<F, U>(self, mut f: F) -> EntityMap<U>
     where
         F: FnMut(T) -> U,
     {
-        EntityMap(
-            self.0
+        EntityMap {
+            components: self
+                .components
                 .into_iter()
                 .map(|(name, comp)| (name, f(comp)))
                 .collect(),
-        )
+            removed: self.removed,
+            children: self
+                .children
+                .map(|children| children.into_iter().map(f).collect()),
+        }
+    }
+}
+
+impl<T> FromIterator<(String, T)> for EntityMap<T> {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        EntityMap {
+            components: iter.into_iter().collect(),
+            removed: BTreeSet::new(),
+            children: None,
+        }
     }
 }
 
@@ -76,12 +162,27 @@ impl<T> EntityMap<T> {
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Value {
-    KeyMap(HashMap<String, Value>),
+    /// Map keeping its keys in the order they were written
+    KeyMap(IndexMap<String, Value>),
     String(String),
+    Bool(bool),
     I32(i32),
+    I64(i64),
+    U64(u64),
     F32(f32),
+    F64(f64),
+    Unit,
+    Option(Option<Box<Value>>),
     Vec(Vec<Value>),
     Entity(EntityMap<Value>),
+    /// Named struct or enum variant as written in RON: `Name`, `Name(a, b)`
+    /// or `Name(field: a)`, holding [`Value::Unit`], [`Value::Vec`] or
+    /// [`Value::KeyMap`]
+    Named(String, Box<Value>),
+    /// Reference to the blueprint at an asset path
+    Blueprint(String),
+    /// Reference to any other asset by path, such as `models/corgi.glb#Scene0`
+    Asset(String),
 }
 
 impl Value {
@@ -89,18 +190,39 @@ impl Value {
     where
         T: Deserialize<'a>,
     {
-        let d = deser::ValueDeserializer { value: self };
-        T::deserialize(d)
+        T::deserialize(self.deserializer())
+    }
+
+    /// Value of `component` as it would be written in a blueprint, the
+    /// counterpart of [`Value::to_component`]
+    pub fn from_component<T>(component: &T) -> Result<Value, ToComponentError>
+    where
+        T: Serialize + ?Sized,
+    {
+        component.serialize(ser::ValueSerializer { struct_names: true })
+    }
+
+    pub(crate) fn deserializer(&self) -> deser::ValueDeserializer<'_> {
+        deser::ValueDeserializer { value: self }
     }
 
     pub fn typename(&self) -> &'static str {
         match self {
             Value::KeyMap(_) => "map",
             Value::String(_) => "string",
+            Value::Bool(_) => "bool",
             Value::I32(_) => "i32",
+            Value::I64(_) => "i64",
+            Value::U64(_) => "u64",
             Value::F32(_) => "f32",
+            Value::F64(_) => "f64",
+            Value::Unit => "unit",
+            Value::Option(_) => "option",
             Value::Vec(_) => "vec",
             Value::Entity(_) => "entity",
+            Value::Named(..) => "named",
+            Value::Blueprint(_) => "blueprint",
+            Value::Asset(_) => "asset",
         }
     }
 }
@@ -0,0 +1,123 @@
+//! Converting blueprint numbers to the numeric type they are written into
+
+use std::convert::TryFrom;
+
+use super::Value;
+
+/// Numeric type a blueprint number can be converted to, failing when the
+/// number is out of its range
+pub(crate) trait FromNumber: Sized {
+    const NAME: &'static str;
+
+    fn from_i64(v: i64) -> Option<Self>;
+
+    fn from_u64(v: u64) -> Option<Self>;
+
+    fn from_f64(v: f64) -> Option<Self>;
+}
+
+macro_rules! impl_from_number_int {
+    ($($ty:ident),*) => {
+        $(
+            impl FromNumber for $ty {
+                const NAME: &'static str = stringify!($ty);
+
+                fn from_i64(v: i64) -> Option<Self> {
+                    $ty::try_from(v).ok()
+                }
+
+                fn from_u64(v: u64) -> Option<Self> {
+                    $ty::try_from(v).ok()
+                }
+
+                /// Only whole numbers convert to integers
+                fn from_f64(v: f64) -> Option<Self> {
+                    if v.fract() != 0.0 {
+                        None
+                    } else if (0.0..18_446_744_073_709_551_616.0).contains(&v) {
+                        $ty::try_from(v as u64).ok()
+                    } else if (-9_223_372_036_854_775_808.0..0.0).contains(&v) {
+                        $ty::try_from(v as i64).ok()
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_number_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+macro_rules! impl_from_number_float {
+    ($($ty:ident),*) => {
+        $(
+            impl FromNumber for $ty {
+                const NAME: &'static str = stringify!($ty);
+
+                fn from_i64(v: i64) -> Option<Self> {
+                    Some(v as $ty)
+                }
+
+                fn from_u64(v: u64) -> Option<Self> {
+                    Some(v as $ty)
+                }
+
+                fn from_f64(v: f64) -> Option<Self> {
+                    let converted = v as $ty;
+                    if converted.is_infinite() && v.is_finite() {
+                        None
+                    } else {
+                        Some(converted)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_number_float!(f32, f64);
+
+/// The `f64` written like `v`, `0.1` for `0.1f32` where `v as f64` gives
+/// `0.10000000149011612`
+pub(crate) fn widen_f32(v: f32) -> f64 {
+    if v.is_finite() {
+        v.to_string().parse().unwrap_or(f64::from(v))
+    } else {
+        f64::from(v)
+    }
+}
+
+impl Value {
+    pub fn is_number(&self) -> bool {
+        matches!(
+            self,
+            Value::I32(_) | Value::I64(_) | Value::U64(_) | Value::F32(_) | Value::F64(_)
+        )
+    }
+
+    /// Converts a number to `T`, `None` if `self` is not a number or is out
+    /// of range for `T`
+    pub(crate) fn to_number<T: FromNumber>(&self) -> Option<T> {
+        match self {
+            Value::I32(v) => T::from_i64(i64::from(*v)),
+            Value::I64(v) => T::from_i64(*v),
+            Value::U64(v) => T::from_u64(*v),
+            Value::F32(v) => T::from_f64(widen_f32(*v)),
+            Value::F64(v) => T::from_f64(*v),
+            _ => None,
+        }
+    }
+
+    /// Number as it is written, for errors
+    pub(crate) fn number_string(&self) -> String {
+        match self {
+            Value::I32(v) => v.to_string(),
+            Value::I64(v) => v.to_string(),
+            Value::U64(v) => v.to_string(),
+            Value::F32(v) => v.to_string(),
+            Value::F64(v) => v.to_string(),
+            _ => self.typename().to_string(),
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Writing values back out in the syntax blueprints are read from, and
+//! serializing components into values
+
+use std::convert::TryFrom;
+
+use indexmap::IndexMap;
+use serde::ser::{self, Serialize, SerializeMap, Serializer};
+
+use super::{deser::Error, number::widen_f32, EntityMap, Value};
+
+/// Name of the newtype struct wrapping named values, formats that keep names
+/// like RON look for it to write `Name(field: a)`, others write the
+/// `$named` call it wraps
+pub(crate) const NAMED_MARKER: &str = "$named";
+
+/// Writes `{ "$name": args }`
+pub(crate) fn serialize_func<S, T>(serializer: S, name: &str, args: &T) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize + ?Sized,
+{
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(&format!("${}", name), args)?;
+    map.end()
+}
+
+struct NamedCall<'a, T> {
+    name: &'a str,
+    /// `None` for unit structs and variants
+    content: Option<&'a T>,
+}
+
+impl<'a, T: Serialize> Serialize for NamedCall<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.content {
+            Some(content) => serialize_func(serializer, "named", &(self.name, content)),
+            None => serialize_func(serializer, "named", self.name),
+        }
+    }
+}
+
+/// Writes a named struct or variant, `content` being `None` for unit ones
+pub(crate) fn serialize_named<S, T>(
+    serializer: S,
+    name: &str,
+    content: Option<&T>,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    serializer.serialize_newtype_struct(NAMED_MARKER, &NamedCall { name, content })
+}
+
+/// Components in order followed by the `$children`, removed components are
+/// left to the blueprint writing the entity
+impl<T: Serialize> Serialize for EntityMap<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, component) in self.components() {
+            map.serialize_entry(name, component)?;
+        }
+        if let Some(children) = self
+            .children
+            .as_ref()
+            .filter(|children| !children.is_empty())
+        {
+            map.serialize_entry("$children", children)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::KeyMap(map) => serializer.collect_map(map),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::I32(v) => serializer.serialize_i32(*v),
+            Value::I64(v) => serializer.serialize_i64(*v),
+            Value::U64(v) => serializer.serialize_u64(*v),
+            // Some formats widen `f32`s with `as f64`, printing `0.1` as
+            // `0.10000000149011612`
+            Value::F32(v) => serializer.serialize_f64(widen_f32(*v)),
+            Value::F64(v) => serializer.serialize_f64(*v),
+            Value::Unit => serializer.serialize_unit(),
+            Value::Option(None) => serializer.serialize_none(),
+            Value::Option(Some(value)) => serializer.serialize_some(value),
+            Value::Vec(values) => serializer.collect_seq(values),
+            Value::Entity(entity) => serialize_func(serializer, "entity", entity),
+            Value::Named(name, content) => match **content {
+                Value::Unit => serialize_named::<_, Value>(serializer, name, None),
+                _ => serialize_named(serializer, name, Some(content)),
+            },
+            Value::Blueprint(path) => serialize_func(serializer, "blueprint", path),
+            Value::Asset(path) => serialize_func(serializer, "asset", path),
+        }
+    }
+}
+
+/// Integers are the smallest of `i32`, `i64` and `u64` they fit, like numbers
+/// read from a blueprint
+fn int_value(v: i128) -> Result<Value, Error> {
+    if let Ok(v) = i32::try_from(v) {
+        Ok(Value::I32(v))
+    } else if let Ok(v) = i64::try_from(v) {
+        Ok(Value::I64(v))
+    } else if let Ok(v) = u64::try_from(v) {
+        Ok(Value::U64(v))
+    } else {
+        Err(ser::Error::custom(format!("{} is out of range for u64", v)))
+    }
+}
+
+fn named(name: &str, content: Value) -> Value {
+    Value::Named(name.to_string(), Box::new(content))
+}
+
+/// Serializes a component into the [`Value`] that deserializes back into it,
+/// structs and enum variants becoming [`Value::Named`]
+#[derive(Clone, Copy)]
+pub(crate) struct ValueSerializer {
+    /// Whether structs are named, otherwise they are written as maps and
+    /// lists, like the types blueprint files are read into
+    pub struct_names: bool,
+}
+
+impl ValueSerializer {
+    /// Wraps `content` in the name of a struct when they are kept
+    fn struct_named(self, name: &str, content: Value) -> Value {
+        if self.struct_names {
+            named(name, content)
+        } else {
+            content
+        }
+    }
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        int_value(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        int_value(v.into())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Vec(
+            v.iter().map(|byte| Value::I32((*byte).into())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Option(None))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        Ok(Value::Option(Some(Box::new(value.serialize(self)?))))
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Value, Error> {
+        Ok(self.struct_named(name, Value::Unit))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(named(variant, Value::Unit))
+    }
+
+    /// `Name(value)`, which deserializes back as a tuple of one value, values
+    /// written with [`serialize_named`] are read back from their `$named`
+    /// call
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = value.serialize(self)?;
+        if name != NAMED_MARKER {
+            return Ok(self.struct_named(name, Value::Vec(vec![value])));
+        }
+
+        let call = match value {
+            Value::KeyMap(call) if call.len() == 1 => call.into_iter().next(),
+            _ => None,
+        };
+        match call {
+            Some((_, Value::String(name))) => Ok(named(&name, Value::Unit)),
+            Some((_, Value::Vec(args))) => match <[Value; 2]>::try_from(args) {
+                Ok([Value::String(name), content]) => Ok(named(&name, content)),
+                _ => Err(ser::Error::custom("expected a name and content")),
+            },
+            _ => Err(ser::Error::custom("expected a `$named` call")),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(named(variant, Value::Vec(vec![value.serialize(self)?])))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(self, None, len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(self, None, Some(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        let name = Some(name).filter(|_| self.struct_names);
+        Ok(SeqSerializer::new(self, name, Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(self, Some(variant), Some(len)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(self, None))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<MapSerializer, Error> {
+        let name = Some(name).filter(|_| self.struct_names);
+        Ok(MapSerializer::new(self, name))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(self, Some(variant)))
+    }
+}
+
+/// Wraps `content` in `name` for named structs and variants
+fn maybe_named(name: Option<&str>, content: Value) -> Value {
+    match name {
+        Some(name) => named(name, content),
+        None => content,
+    }
+}
+
+pub(crate) struct SeqSerializer {
+    serializer: ValueSerializer,
+    name: Option<&'static str>,
+    values: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn new(serializer: ValueSerializer, name: Option<&'static str>, len: Option<usize>) -> Self {
+        SeqSerializer {
+            serializer,
+            name,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(value.serialize(self.serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        maybe_named(self.name, Value::Vec(self.values))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+pub(crate) struct MapSerializer {
+    serializer: ValueSerializer,
+    name: Option<&'static str>,
+    entries: IndexMap<String, Value>,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn new(serializer: ValueSerializer, name: Option<&'static str>) -> Self {
+        MapSerializer {
+            serializer,
+            name,
+            entries: IndexMap::new(),
+            key: None,
+        }
+    }
+
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        self.entries.insert(key, value.serialize(self.serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        maybe_named(self.name, Value::KeyMap(self.entries))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    /// Map keys are strings, like they are in blueprints
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(self.serializer)? {
+            Value::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            key => Err(ser::Error::custom(format!(
+                "map keys must be strings, got {}",
+                key.typename()
+            ))),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("map value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}